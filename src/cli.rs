@@ -0,0 +1,267 @@
+use std::path::{Path, PathBuf};
+use chrono::{Local, Utc};
+use tokio::fs::DirBuilder;
+use anyhow::Result;
+use thiserror::Error;
+
+use crate::utils::{Json, Map};
+use crate::config::{Config, ConfigFeedEntry};
+use crate::feeds::{Entry, Feed};
+use crate::providers::{Providers, validate_provider, validate_provider_data};
+use crate::interfaces::local_instance;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+	Json,
+	Table,
+}
+
+impl std::str::FromStr for Format {
+	type Err = UnknownFormatError;
+	
+	fn from_str(format: &str) -> Result<Self, Self::Err> {
+		match format {
+			"json" => Ok(Format::Json),
+			"table" => Ok(Format::Table),
+			_ => Err(UnknownFormatError),
+		}
+	}
+}
+
+fn truncate(text: &str, length: usize) -> String {
+	let text = text.lines().next().unwrap_or_default();
+	
+	if text.chars().count() > length {
+		text.chars().take(length - 1).chain(Some('…')).collect()
+	} else {
+		text.to_string()
+	}
+}
+
+fn print_table(headers: &[&str], rows: Vec<Vec<String>>) {
+	let rows = rows.into_iter()
+	               .map(|row| row.into_iter().map(|cell| truncate(&cell, 60)).collect::<Vec<_>>())
+	               .collect::<Vec<_>>();
+	
+	let widths = headers.iter()
+	                    .enumerate()
+	                    .map(|(n, header)| rows.iter().map(|row| row[n].chars().count()).max().unwrap_or(0).max(header.len()))
+	                    .collect::<Vec<_>>();
+	
+	let print_row = |row: &[&str]| {
+		let line = row.iter()
+		              .zip(widths.iter())
+		              .map(|(cell, width)| format!("{}{}", cell, " ".repeat(width - cell.chars().count())))
+		              .collect::<Vec<_>>()
+		              .join("  ");
+		println!("{}", line.trim_end());
+	};
+	
+	print_row(headers);
+	for row in rows.iter() {
+		print_row(&row.iter().map(String::as_str).collect::<Vec<_>>());
+	}
+}
+
+fn entry_row(kind: &str, entry: &Entry) -> Vec<String> {
+	vec![
+		kind.to_string(),
+		entry.timestamp.map(|ts| ts.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default(),
+		entry.title.clone(),
+		entry.link.clone().or(entry.description.clone()).unwrap_or_default(),
+	]
+}
+
+// Private copy of the data directory, so providers know what the running instance has seen,
+// but anything they store, eg. seen releases, doesn't change it
+async fn copy_data_dir(data_dir: &Path) -> Result<PathBuf> {
+	let dir = std::env::temp_dir().join(format!("rust-notifier-fetch-{}-{}", std::process::id(), Utc::now().timestamp_micros()));
+	DirBuilder::new().mode(0o700).create(&dir).await?;
+	
+	if let Ok(mut entries) = tokio::fs::read_dir(data_dir).await {
+		while let Some(entry) = entries.next_entry().await? {
+			if entry.file_type().await?.is_file() {
+				tokio::fs::copy(entry.path(), dir.join(entry.file_name())).await?;
+			}
+		}
+	}
+	
+	Ok(dir)
+}
+
+// Runs provider of a single feed once
+pub async fn fetch(config: Config, name: &str, format: Format) -> Result<()> {
+	let feed_entry = config.feeds.get(name).ok_or(FeedNotFoundError)?;
+	let provider_config = config.providers.get(&feed_entry.provider).ok_or(ProviderNotConfiguredError)?;
+	
+	let data_dir = copy_data_dir(Path::new(&config.data_dir)).await?;
+	let mut providers = Providers::new(Map::from([(feed_entry.provider.clone(), provider_config.clone())]), data_dir.clone());
+	let feeds = providers.fetch_feeds(&Map::from([(name.to_string(), feed_entry.clone())]), reqwest::Client::new()).await;
+	let feed = feeds.get(name).cloned().unwrap_or_else(Feed::new);
+	tokio::fs::remove_dir_all(&data_dir).await?;
+	
+	match format {
+		Format::Json => println!("{}", serde_json::to_string_pretty(&feed)?),
+		Format::Table => {
+			let rows = feed.status.iter().map(|entry| entry_row("status", entry))
+			               .chain(feed.notifications.iter().map(|entry| entry_row("notification", entry)))
+			               .chain(feed.errors.iter().map(|entry| entry_row("error", entry)))
+			               .collect();
+			
+			print_table(&["KIND", "TIME", "TITLE", "LINK"], rows);
+		},
+	}
+	
+	Ok(())
+}
+
+// Lists feeds of running instance, through the web interface. URL and token default to the ones in web config
+pub async fn list(config: Config, url: Option<String>, token: Option<String>, format: Format) -> Result<()> {
+	let instance = config.interfaces.get("web").map(|web| local_instance(web.clone()));
+	
+	let (url, token) = match (url, instance) {
+		(Some(url), instance) => (url, token.or(instance.and_then(Result::ok).and_then(|instance| instance.token))),
+		(None, Some(instance)) => {
+			let instance = instance?;
+			(instance.url, token.or(instance.token))
+		},
+		(None, None) => return Err(WebNotConfiguredError.into()),
+	};
+	
+	let mut request = reqwest::Client::new()
+	                          .get(format!("{}/feeds", url.trim_end_matches('/')));
+	
+	if let Some(token) = token {
+		request = request.bearer_auth(token);
+	}
+	
+	let feeds: Map<Feed> = request.send()
+	                              .await?
+	                              .error_for_status()?
+	                              .json()
+	                              .await?;
+	
+	match format {
+		Format::Json => println!("{}", serde_json::to_string_pretty(&feeds)?),
+		Format::Table => {
+			let rows = feeds.iter()
+			                .map(|(name, feed)| vec![
+				                name.clone(),
+				                feed.status.len().to_string(),
+				                feed.notifications.len().to_string(),
+				                feed.errors.len().to_string(),
+				                feed.status.first().or(feed.notifications.first()).map(|entry| entry.title.clone()).unwrap_or_default(),
+			                ])
+			                .collect();
+			
+			print_table(&["FEED", "STATUS", "NOTIFICATIONS", "ERRORS", "LATEST"], rows);
+		},
+	}
+	
+	Ok(())
+}
+
+// Prints all problems found in the config, fails if there are any
+pub async fn validate_config(path: &Path) -> Result<()> {
+	let content = tokio::fs::read(path).await?;
+	let config: Config = serde_json::from_slice(&content)?;
+	let mut problems = vec![];
+	
+	if config.fetch_interval_secs == 0 {
+		problems.push("fetchIntervalSecs has to be greater than 0".to_string());
+	}
+	
+	for (name, provider_config) in config.providers.iter() {
+		if let Err(err) = validate_provider(name, provider_config.clone(), Path::new(&config.data_dir)) {
+			problems.push(format!("Provider {}: {}", name, err));
+		}
+	}
+	
+	for (name, feed) in config.feeds.iter() {
+		match config.providers.get(&feed.provider) {
+			None => problems.push(format!("Feed {}: provider {} is not configured", name, feed.provider)),
+			Some(provider_config) if provider_config["enabled"] == Json::Bool(false) => {
+				problems.push(format!("Feed {}: provider {} is disabled", name, feed.provider));
+			},
+			Some(_) => {},
+		}
+		
+		if let Err(err) = validate_provider_data(&feed.provider, &feed.provider_data) {
+			problems.push(format!("Feed {}: invalid providerData: {}", name, err));
+		}
+	}
+	
+	for (name, interface_config) in config.interfaces.iter() {
+		if !interface_config["enabled"].is_boolean() {
+			problems.push(format!("Interface {}: missing enabled field", name));
+		}
+	}
+	
+	if problems.is_empty() {
+		println!("{} is valid", path.to_string_lossy());
+		Ok(())
+	} else {
+		for problem in problems.iter() {
+			println!("{}", problem);
+		}
+		Err(InvalidConfigError(problems.len()).into())
+	}
+}
+
+pub async fn add_feed(path: &Path, name: &str, provider: String, data: Option<String>, color: Option<String>) -> Result<()> {
+	let mut config = Config::load(path).await?;
+	if config.feeds.contains_key(name) { return Err(FeedExistsError.into()) }
+	
+	// Plain strings, eg. URLs, don't have to be quoted
+	let provider_data = match data {
+		Some(data) => serde_json::from_str(&data).unwrap_or(Json::String(data)),
+		None => Json::Null,
+	};
+	
+	// Same check as the web API, so a feed which can't be fetched isn't saved
+	validate_provider_data(&provider, &provider_data).map_err(|err| InvalidProviderDataError(err.to_string()))?;
+	
+	config.feeds.insert(name.to_string(), ConfigFeedEntry { provider, provider_data, color, urgency: None });
+	config.save(path).await?;
+	
+	println!("Added feed {}", name);
+	Ok(())
+}
+
+pub async fn remove_feed(path: &Path, name: &str) -> Result<()> {
+	let mut config = Config::load(path).await?;
+	if config.feeds.remove(name).is_none() { return Err(FeedNotFoundError.into()) }
+	
+	config.save(path).await?;
+	
+	println!("Removed feed {}", name);
+	Ok(())
+}
+
+#[derive(Debug, Copy, Clone, Error)]
+#[error("Unknown format, expected json or table")]
+pub struct UnknownFormatError;
+
+#[derive(Debug, Copy, Clone, Error)]
+#[error("Feed not found")]
+pub struct FeedNotFoundError;
+
+#[derive(Debug, Copy, Clone, Error)]
+#[error("Feed already exists")]
+pub struct FeedExistsError;
+
+#[derive(Debug, Clone, Error)]
+#[error("Invalid providerData: {0}")]
+pub struct InvalidProviderDataError(String);
+
+#[derive(Debug, Copy, Clone, Error)]
+#[error("Feed's provider is not configured")]
+pub struct ProviderNotConfiguredError;
+
+#[derive(Debug, Copy, Clone, Error)]
+#[error("Web interface is not configured, use --url")]
+pub struct WebNotConfiguredError;
+
+#[derive(Debug, Copy, Clone, Error)]
+#[error("Found {0} problems in the config")]
+pub struct InvalidConfigError(usize);
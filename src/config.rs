@@ -1,7 +1,9 @@
 use std::path::Path;
-use tokio::fs::File;
+use std::io::ErrorKind;
+use tokio::fs::{File, OpenOptions};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
 use anyhow::Result;
 
 use crate::utils::*;
@@ -13,14 +15,67 @@ pub struct Config {
 	pub interfaces: Map<Json>,
 	#[serde(rename="fetchIntervalSecs")]
 	pub fetch_interval_secs: u64,
+	#[serde(rename="dataDir", default = "default_data_dir")]
+	pub data_dir: String,
+	#[serde(default)]
+	pub log: LogConfig,
+}
+
+fn default_data_dir() -> String {
+	"data".to_string()
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct LogConfig {
+	// Filter directives, eg. "info,rust_notifier::providers::youtube=error". RUST_LOG overrides it
+	#[serde(default = "default_log_level")]
+	pub level: String,
+	#[serde(default)]
+	pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+	fn default() -> Self {
+		LogConfig {
+			level: default_log_level(),
+			format: LogFormat::default(),
+		}
+	}
+}
+
+fn default_log_level() -> String {
+	"info".to_string()
+}
+
+#[derive(Deserialize, Serialize, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+	#[default]
+	Text,
+	// One JSON object per line, for log aggregators
+	Json,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, ToSchema)]
 pub struct ConfigFeedEntry {
 	pub provider: String,
+	// Format depends on the provider
 	#[serde(rename="providerData")]
+	#[schema(value_type = Value)]
 	pub provider_data: Json,
-	pub color: Option<String>
+	pub color: Option<String>,
+	// Used by desktop notifications
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub urgency: Option<Urgency>,
+}
+
+#[derive(Deserialize, Serialize, Copy, Clone, Debug, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+	Low,
+	#[default]
+	Normal,
+	Critical,
 }
 
 impl Config {
@@ -34,7 +89,7 @@ impl Config {
 				Ok(config)
 			},
 			Err(_) => {
-				println!("{} not found.\nGenerating new from config_example.json.", path.as_ref().to_string_lossy());
+				eprintln!("{} not found.\nGenerating new from config_example.json.", path.as_ref().to_string_lossy());
 				
 				let mut example = File::open("config_example.json").await?;
 				let mut example_content = vec![];
@@ -47,9 +102,32 @@ impl Config {
 		}
 	}
 	
+	// Fails when the file is missing instead of generating a new one, for changing the config at runtime
+	pub async fn read(path: impl AsRef<Path>) -> Result<Config> {
+		let content = tokio::fs::read(path).await?;
+		Ok(serde_json::from_slice(&content)?)
+	}
+	
+	// Written to temporary file first, so a crash doesn't leave truncated config behind.
+	// It contains passwords and tokens, so it's private until it gets permissions of the replaced file.
 	pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
-		let mut file = File::create(&path).await?;
+		let path = path.as_ref();
+		let tmp = path.with_extension("tmp");
+		
+		match tokio::fs::remove_file(&tmp).await {
+			Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+			_ => {},
+		}
+		
+		let mut file = OpenOptions::new().write(true).create_new(true).mode(0o600).open(&tmp).await?;
 		file.write_all(serde_json::to_string_pretty(self)?.as_bytes()).await?;
+		file.sync_all().await?;
+		
+		if let Ok(metadata) = tokio::fs::metadata(path).await {
+			tokio::fs::set_permissions(&tmp, metadata.permissions()).await?;
+		}
+		
+		tokio::fs::rename(&tmp, path).await?;
 		Ok(())
 	}
 }
@@ -1,11 +1,12 @@
 use std::cmp::Ordering;
-use serde::Serialize;
+use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
 use chrono::{DateTime, Utc, TimeZone};
 use chrono::serde::ts_milliseconds_option;
 
 use crate::utils::Json;
 
-#[derive(Serialize, Clone, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, Eq, PartialEq)]
 pub struct Entry {
 	pub title: String,
 	pub guid: String,
@@ -16,8 +17,12 @@ pub struct Entry {
 	pub color: Option<String>,
 	#[serde(rename="imageURL")]
 	pub image_url: Option<String>,
+	// Milliseconds since epoch
 	#[serde(with = "ts_milliseconds_option")]
+	#[schema(value_type = Option<i64>)]
 	pub timestamp: Option<DateTime<Utc>>,
+	// Provider specific data
+	#[schema(value_type = Option<Object>)]
 	pub extra: Option<Json>,
 }
 
@@ -1,11 +1,12 @@
-use serde::Serialize;
+use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
 use chrono::Utc;
 use anyhow::Error;
 use serde_json::json;
 use super::Entry;
 use crate::utils::hash;
 
-#[derive(Serialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
 pub struct Feed {
 	pub status: Vec<Entry>,
 	pub notifications: Vec<Entry>,
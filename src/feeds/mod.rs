@@ -1,5 +1,7 @@
-use std::collections::HashSet;
+use std::cmp::Reverse;
+use std::collections::HashMap;
 use chrono::{DateTime, Utc};
+use regex::Regex;
 
 mod entry;
 pub use entry::Entry;
@@ -8,19 +10,86 @@ mod feed;
 pub use feed::Feed;
 use crate::utils::Map;
 
+// Guids remembered per feed, older ones are forgotten first
+const MAX_SEEN: usize = 1000;
+
+// Guid of each entry seen in a feed, with the snapshot it was last seen in
+type Seen = HashMap<String, DateTime<Utc>>;
+
 #[derive(Debug)]
 pub struct Feeds {
 	pub created: DateTime<Utc>,
 	pub feeds: Map<Feed>,
-	pub ids: Option<HashSet<String>>
+	pub ids: Map<Seen>,
 }
 
 impl Feeds {
 	pub fn new() -> Self { Feeds{
 		created: Utc::now(),
 		feeds: Map::new(),
-		ids: None,
+		ids: Map::new(),
 	}}
+	
+	// Remembers which entries were seen recently, so the next snapshot can tell new entries apart from
+	// updated ones (same feed and guid, eg. a changed score) and from ones which are back after a failed
+	// fetch or after dropping out of the feed for a while, eg. below the score threshold.
+	pub fn track(&mut self, previous: &Feeds) {
+		let created = self.created;
+		
+		self.ids = self.feeds
+		               .iter()
+		               .filter_map(|(name, feed)| {
+			               let previous = previous.ids.get(name);
+			               
+			               // Feeds which were never fetched successfully have nothing to compare to yet
+			               if previous.is_none() && failed(feed) { return None }
+			               
+			               let mut seen = previous.cloned().unwrap_or_default();
+			               seen.extend(tracked(feed).map(|entry| (entry.guid.clone(), created)));
+			               
+			               Some((name.clone(), forget_oldest(seen, created)))
+		               })
+		               .collect();
+	}
+	
+	// Feeds not tracked in the previous snapshot, eg. added at runtime, have nothing new either
+	pub fn is_new(&self, previous: &Feeds, name: &str, entry: &Entry) -> bool {
+		previous.ids.get(name).is_some_and(|seen| !seen.contains_key(&entry.guid))
+	}
+	
+	// Feeds with name matching the filter, all of them without one
+	pub fn filtered<'a>(&'a self, filter: Option<&'a Regex>) -> impl Iterator<Item = (&'a String, &'a Feed)> + Clone + 'a {
+		self.feeds.iter()
+		          .filter(move |(name, _)| filter.is_none_or(|filter| filter.is_match(name)))
+	}
+	
+	// Entries that were not present in the previous snapshot. Nothing is new on the first fetch.
+	pub fn new_entries<'a>(&'a self, previous: &'a Feeds) -> impl Iterator<Item = &'a Entry> + 'a {
+		self.feeds.iter()
+		          .flat_map(move |(name, feed)| tracked(feed).filter(move |entry| self.is_new(previous, name, entry)))
+	}
+}
+
+// Errors get a new guid on every failed fetch, so they would be new every time
+fn tracked(feed: &Feed) -> impl Iterator<Item = &Entry> {
+	feed.status.iter().chain(feed.notifications.iter())
+}
+
+// Only errors, the previous entries are still there but weren't fetched this time
+fn failed(feed: &Feed) -> bool {
+	feed.status.is_empty() && feed.notifications.is_empty() && !feed.errors.is_empty()
+}
+
+// Entries of the current snapshot are always kept
+fn forget_oldest(seen: Seen, created: DateTime<Utc>) -> Seen {
+	if seen.len() <= MAX_SEEN { return seen }
+	
+	let current = seen.values().filter(|&&last_seen| last_seen == created).count();
+	let mut seen = seen.into_iter().collect::<Vec<_>>();
+	seen.sort_by_key(|&(_, last_seen)| Reverse(last_seen));
+	seen.truncate(MAX_SEEN.max(current));
+	
+	seen.into_iter().collect()
 }
 
 impl std::ops::Deref for Feeds {
@@ -37,4 +106,88 @@ impl std::ops::DerefMut for Feeds {
 	}
 }
 
-
+#[cfg(test)]
+mod tests {
+	use anyhow::anyhow;
+	use super::*;
+	
+	fn snapshot(previous: &Feeds, feeds: Vec<(&str, Feed)>) -> Feeds {
+		let mut snapshot = Feeds::new();
+		snapshot.feeds = feeds.into_iter().map(|(name, feed)| (name.to_string(), feed)).collect();
+		snapshot.track(previous);
+		snapshot
+	}
+	
+	fn feed(guids: &[&str]) -> Feed {
+		let mut feed = Feed::new();
+		feed.notifications = guids.iter().map(|guid| Entry::new(guid, guid)).collect();
+		feed
+	}
+	
+	fn error() -> Feed {
+		Feed::from_err("Unable to fetch", &anyhow!("timeout"))
+	}
+	
+	fn titles<'a>(entries: impl Iterator<Item = &'a Entry>) -> Vec<&'a str> {
+		entries.map(|entry| entry.title.as_str()).collect()
+	}
+	
+	#[test]
+	fn repeated_errors_are_not_new() {
+		let first = snapshot(&Feeds::new(), vec![("news", feed(&["1"]))]);
+		let second = snapshot(&first, vec![("news", error())]);
+		let third = snapshot(&second, vec![("news", error())]);
+		
+		assert!(second.new_entries(&first).next().is_none());
+		assert!(third.new_entries(&second).next().is_none());
+	}
+	
+	#[test]
+	fn entries_are_not_new_after_failed_fetch() {
+		let first = snapshot(&Feeds::new(), vec![("news", feed(&["1", "2"]))]);
+		let second = snapshot(&first, vec![("news", error())]);
+		let third = snapshot(&second, vec![("news", feed(&["1", "2", "3"]))]);
+		
+		assert_eq!(titles(third.new_entries(&second)), ["3"]);
+	}
+	
+	#[test]
+	fn returning_entries_are_not_new() {
+		let first = snapshot(&Feeds::new(), vec![("news", feed(&["1", "2"]))]);
+		let second = snapshot(&first, vec![("news", feed(&["1"]))]);
+		let third = snapshot(&second, vec![("news", feed(&["1", "2"]))]);
+		
+		assert!(third.new_entries(&second).next().is_none());
+	}
+	
+	#[test]
+	fn first_successful_fetch_is_not_new() {
+		let first = snapshot(&Feeds::new(), vec![("news", error())]);
+		let second = snapshot(&first, vec![("news", feed(&["1"]))]);
+		let third = snapshot(&second, vec![("news", feed(&["1", "2"]))]);
+		
+		assert!(second.new_entries(&first).next().is_none());
+		assert_eq!(titles(third.new_entries(&second)), ["2"]);
+		
+		assert!(second.new_entries(&first).next().is_none());
+	}
+	
+	#[test]
+	fn only_added_entries_are_new() {
+		let first = snapshot(&Feeds::new(), vec![("news", feed(&["1"]))]);
+		let second = snapshot(&first, vec![("news", feed(&["1", "2"])), ("added", feed(&["3"]))]);
+		
+		assert!(titles(first.new_entries(&Feeds::new())).is_empty());
+		assert_eq!(titles(second.new_entries(&first)), ["2"]);
+	}
+	
+	#[test]
+	fn forgets_oldest_entries() {
+		let guids = (0..MAX_SEEN).map(|i| i.to_string()).collect::<Vec<_>>();
+		let first = snapshot(&Feeds::new(), vec![("news", feed(&guids.iter().map(String::as_str).collect::<Vec<_>>()))]);
+		let second = snapshot(&first, vec![("news", feed(&["new"]))]);
+		
+		assert_eq!(second.ids["news"].len(), MAX_SEEN);
+		assert!(second.ids["news"].contains_key("new"));
+	}
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use serde::Deserialize;
+use regex::{Regex, RegexBuilder};
+use futures::StreamExt;
+use tokio::sync::broadcast::error::RecvError;
+use zbus::zvariant::Value;
+use anyhow::Result;
+use tracing::{info, warn, error};
+
+use crate::utils::{Json, hash};
+use crate::state::State;
+use crate::feeds::Entry;
+use crate::config::Urgency;
+
+#[derive(Deserialize)]
+struct DesktopConfig {
+	// Regex, only entries of matching feeds raise notifications
+	filter: Option<String>,
+	#[serde(rename="appName", default = "default_app_name")]
+	app_name: String,
+	// -1 leaves it to the notification server
+	#[serde(rename="timeoutMs", default = "default_timeout")]
+	timeout_ms: i32,
+	// Command used to open links
+	#[serde(default = "default_opener")]
+	opener: String,
+	// D-Bus address, session bus by default
+	address: Option<String>,
+}
+
+fn default_app_name() -> String { "rust-notifier".to_string() }
+fn default_timeout() -> i32 { -1 }
+fn default_opener() -> String { "xdg-open".to_string() }
+
+#[zbus::proxy(
+	interface = "org.freedesktop.Notifications",
+	default_service = "org.freedesktop.Notifications",
+	default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+	#[allow(clippy::too_many_arguments)]
+	fn notify(&self,
+	          app_name: &str,
+	          replaces_id: u32,
+	          app_icon: &str,
+	          summary: &str,
+	          body: &str,
+	          actions: &[&str],
+	          hints: HashMap<&str, Value<'_>>,
+	          expire_timeout: i32) -> zbus::Result<u32>;
+	
+	#[zbus(signal)]
+	fn action_invoked(&self, id: u32, action_key: String) -> zbus::Result<()>;
+	
+	#[zbus(signal)]
+	fn notification_closed(&self, id: u32, reason: u32) -> zbus::Result<()>;
+}
+
+fn urgency_level(urgency: Urgency) -> u8 {
+	match urgency {
+		Urgency::Low => 0,
+		Urgency::Normal => 1,
+		Urgency::Critical => 2,
+	}
+}
+
+// Notification servers only accept local files as icons
+async fn download_image(client: &reqwest::Client, url: &str) -> Result<PathBuf> {
+	let dir = std::env::temp_dir().join("rust-notifier");
+	let extension = url.rsplit('/')
+	                   .next()
+	                   .and_then(|name| name.split('?').next())
+	                   .and_then(|name| name.rsplit_once('.'))
+	                   .map(|(_, extension)| extension)
+	                   .filter(|extension| extension.len() <= 4)
+	                   .unwrap_or("img");
+	let path = dir.join(format!("{}.{}", hash(&url), extension));
+	
+	if tokio::fs::try_exists(&path).await? {
+		return Ok(path);
+	}
+	
+	let bytes = client.get(url)
+	                  .send()
+	                  .await?
+	                  .error_for_status()?
+	                  .bytes()
+	                  .await?;
+	
+	tokio::fs::create_dir_all(&dir).await?;
+	tokio::fs::write(&path, &bytes).await?;
+	
+	Ok(path)
+}
+
+struct Notifier<'a> {
+	proxy: NotificationsProxy<'a>,
+	client: reqwest::Client,
+	config: DesktopConfig,
+	filter: Option<Regex>,
+	// Notification ID -> Link
+	links: HashMap<u32, String>,
+}
+
+impl Notifier<'_> {
+	async fn notify(&mut self, entry: &Entry, urgency: Urgency) -> Result<()> {
+		if let Some(filter) = &self.filter {
+			if !entry.feed_name.as_ref().is_some_and(|name| filter.is_match(name)) { return Ok(()) }
+		}
+		
+		let icon = match &entry.image_url {
+			Some(url) => match download_image(&self.client, url).await {
+				Ok(path) => Some(path),
+				Err(err) => { warn!(%url, error = %err, "Unable to download notification icon"); None },
+			},
+			None => None,
+		};
+		let icon = icon.as_ref().map(|path| path.to_string_lossy().to_string()).unwrap_or_default();
+		
+		let mut hints = HashMap::new();
+		hints.insert("urgency", Value::from(urgency_level(urgency)));
+		if let Some(feed_name) = &entry.feed_name {
+			hints.insert("category", Value::from(format!("x-rust-notifier.{}", feed_name)));
+		}
+		if !icon.is_empty() {
+			hints.insert("image-path", Value::from(icon.as_str()));
+		}
+		
+		let link = entry.link.as_deref().and_then(web_link);
+		let actions: &[&str] = if link.is_some() { &["default", "Open link", "open", "Open link"] } else { &[] };
+		let body = entry.description.as_deref().unwrap_or_default();
+		
+		let id = self.proxy.notify(&self.config.app_name, 0, &icon, &entry.title, body, actions, hints, self.config.timeout_ms).await?;
+		
+		if let Some(link) = link {
+			self.links.insert(id, link);
+		}
+		
+		Ok(())
+	}
+	
+	fn open(&self, id: u32) {
+		let Some(link) = self.links.get(&id) else { return };
+		
+		if let Err(err) = tokio::process::Command::new(&self.config.opener).arg(link).spawn() {
+			warn!(%link, error = %err, "Unable to open link");
+		}
+	}
+}
+
+// Only web links are opened, links to files or other schemes from feeds could run anything
+fn web_link(link: &str) -> Option<String> {
+	reqwest::Url::parse(link).ok()
+	                         .filter(|url| matches!(url.scheme(), "http" | "https"))
+	                         .map(String::from)
+}
+
+pub async fn serve(config: Json, state: State) -> Result<()> {
+	let config: DesktopConfig = serde_json::from_value(config)?;
+	
+	let connection = match &config.address {
+		Some(address) => zbus::connection::Builder::address(address.as_str())?.build().await?,
+		None => zbus::Connection::session().await?,
+	};
+	let proxy = NotificationsProxy::new(&connection).await?;
+	let mut actions = proxy.receive_action_invoked().await?;
+	let mut closed = proxy.receive_notification_closed().await?;
+	
+	let filter = config.filter
+	                   .as_ref()
+	                   .map(|filter| RegexBuilder::new(filter)
+	                                              .size_limit(1024 * 32)
+	                                              .dfa_size_limit(1024 * 32)
+	                                              .nest_limit(10)
+	                                              .case_insensitive(true)
+	                                              .build())
+	                   .transpose()?;
+	
+	let mut notifier = Notifier { proxy, client: reqwest::Client::new(), config, filter, links: HashMap::new() };
+	let mut receiver = state.new_entries.subscribe();
+	
+	info!("Sending desktop notifications");
+	
+	loop {
+		tokio::select! {
+			received = receiver.recv() => {
+				let entries = match received {
+					Ok(entries) => entries,
+					Err(RecvError::Lagged(skipped)) => { warn!(skipped, "Desktop notifications skipped updates"); continue },
+					Err(RecvError::Closed) => return Ok(()),
+				};
+				
+				let feed_entries = state.feed_entries.load();
+				
+				for entry in entries.iter() {
+					let urgency = entry.feed_name
+					                   .as_ref()
+					                   .and_then(|name| feed_entries.get(name))
+					                   .and_then(|feed_entry| feed_entry.urgency)
+					                   .unwrap_or_default();
+					
+					if let Err(err) = notifier.notify(entry, urgency).await {
+						error!(error = %err, "Unable to send desktop notification");
+					}
+				}
+			},
+			Some(action) = actions.next() => {
+				if let Ok(args) = action.args() {
+					notifier.open(args.id);
+				}
+			},
+			Some(signal) = closed.next() => {
+				if let Ok(args) = signal.args() {
+					notifier.links.remove(&args.id);
+				}
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn opens_only_web_links() {
+		assert_eq!(web_link("https://example.com/a b").as_deref(), Some("https://example.com/a%20b"));
+		assert_eq!(web_link("HTTP://example.com").as_deref(), Some("http://example.com/"));
+		assert_eq!(web_link("file:///etc/passwd"), None);
+		assert_eq!(web_link("javascript:alert(1)"), None);
+		assert_eq!(web_link("steam://run/440"), None);
+		assert_eq!(web_link("--help"), None);
+	}
+}
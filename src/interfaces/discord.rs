@@ -0,0 +1,205 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::json;
+use tokio::sync::Mutex;
+use tokio::sync::broadcast::error::RecvError;
+use anyhow::Result;
+use thiserror::Error;
+use tracing::{info, warn, error};
+
+use crate::utils::Json;
+use crate::state::State;
+use crate::feeds::Entry;
+use super::subscriptions::Subscriptions;
+
+#[derive(Deserialize)]
+struct DiscordConfig {
+	token: String,
+	// Channel IDs in which the bot listens for commands
+	channels: Vec<String>,
+	#[serde(rename="apiUrl", default = "default_api_url")]
+	api_url: String,
+	#[serde(rename="pollSecs", default = "default_poll_secs")]
+	poll_secs: u64,
+	// Relative to the data directory
+	#[serde(default = "default_subscriptions")]
+	subscriptions: String,
+}
+
+fn default_api_url() -> String { "https://discord.com/api/v10".to_string() }
+fn default_poll_secs() -> u64 { 5 }
+fn default_subscriptions() -> String { "discord-subscriptions.json".to_string() }
+
+#[derive(Deserialize)]
+struct Message {
+	id: String,
+	#[serde(default)]
+	content: String,
+	author: Author,
+}
+
+#[derive(Deserialize)]
+struct Author {
+	#[serde(default)]
+	bot: bool,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+	message: String,
+}
+
+// Snowflakes are ordered by time, but can't be compared as strings
+fn snowflake(id: &str) -> u64 {
+	id.parse().unwrap_or(0)
+}
+
+fn embed(entry: &Entry) -> Json {
+	let mut embed = json!({
+		"title": entry.title.chars().take(256).collect::<String>(),
+		"footer": { "text": entry.feed_name },
+	});
+	
+	if let Some(link) = &entry.link { embed["url"] = json!(link) }
+	if let Some(description) = &entry.description { embed["description"] = json!(description.chars().take(1000).collect::<String>()) }
+	if let Some(image_url) = &entry.image_url { embed["thumbnail"] = json!({ "url": image_url }) }
+	if let Some(timestamp) = &entry.timestamp { embed["timestamp"] = json!(timestamp.to_rfc3339()) }
+	if let Some(color) = entry.color.as_ref().and_then(|color| u32::from_str_radix(color.trim_start_matches('#'), 16).ok()) {
+		embed["color"] = json!(color);
+	}
+	
+	embed
+}
+
+struct Discord {
+	client: reqwest::Client,
+	api_url: String,
+	token: String,
+}
+
+impl Discord {
+	async fn call<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> Result<T> {
+		let response = request.header("Authorization", format!("Bot {}", self.token))
+		                      .send()
+		                      .await?;
+		
+		if !response.status().is_success() {
+			let status = response.status();
+			return Err(match response.json::<ErrorResponse>().await {
+				Ok(err) => DiscordError(err.message).into(),
+				Err(_) => DiscordError(status.to_string()).into(),
+			});
+		}
+		
+		Ok(response.json().await?)
+	}
+	
+	async fn messages(&self, channel: &str, after: Option<&str>) -> Result<Vec<Message>> {
+		let mut request = self.client.get(format!("{}/channels/{}/messages", self.api_url, channel))
+		                             .query(&[("limit", "50")]);
+		
+		if let Some(after) = after {
+			request = request.query(&[("after", after)]);
+		}
+		
+		self.call(request).await
+	}
+	
+	async fn send(&self, channel: &str, body: Json) -> Result<()> {
+		let _: Json = self.call(self.client.post(format!("{}/channels/{}/messages", self.api_url, channel)).json(&body)).await?;
+		Ok(())
+	}
+}
+
+async fn poll_channel(discord: &Discord, state: &State, subscriptions: &Mutex<Subscriptions>, channel: &str, poll: Duration) {
+	// Commands sent before the bot started are skipped
+	let mut last = None;
+	
+	loop {
+		let messages = discord.messages(channel, last.as_deref()).await;
+		tokio::time::sleep(poll).await;
+		
+		let mut messages = match messages {
+			Ok(messages) => messages,
+			Err(err) => { warn!(%channel, error = %err, "Unable to get Discord messages"); continue },
+		};
+		
+		messages.sort_by_key(|message| snowflake(&message.id));
+		let skip = last.is_none();
+		if let Some(message) = messages.last() {
+			last = Some(message.id.clone());
+		} else if skip {
+			// Empty channel, start from the beginning
+			last = Some("0".to_string());
+		}
+		if skip { continue }
+		
+		for message in messages {
+			if message.author.bot { continue }
+			let Some(command) = message.content.strip_prefix('!') else { continue };
+			
+			let response = subscriptions.lock().await.command(state, channel, "!", command).await;
+			
+			let result = match response {
+				Ok(Some(response)) => discord.send(channel, json!({ "content": response })).await,
+				Ok(None) => Ok(()),
+				Err(err) => Err(err),
+			};
+			
+			if let Err(err) = result {
+				warn!(error = %err, "Unable to handle Discord command");
+			}
+		}
+	}
+}
+
+async fn send_loop(discord: &Discord, state: &State, subscriptions: &Mutex<Subscriptions>) {
+	let mut receiver = state.new_entries.subscribe();
+	
+	loop {
+		let entries = match receiver.recv().await {
+			Ok(entries) => entries,
+			Err(RecvError::Lagged(skipped)) => { warn!(skipped, "Discord skipped updates"); continue },
+			Err(RecvError::Closed) => return,
+		};
+		
+		let deliveries = subscriptions.lock()
+		                              .await
+		                              .chats(&entries)
+		                              .map(|(channel, entries)| (channel.to_string(), entries.into_iter().map(embed).collect::<Vec<_>>()))
+		                              .collect::<Vec<_>>();
+		
+		for (channel, embeds) in deliveries {
+			// Messages can contain up to 10 embeds
+			for embeds in embeds.chunks(10) {
+				if let Err(err) = discord.send(&channel, json!({ "embeds": embeds })).await {
+					error!(%channel, error = %err, "Unable to send Discord message");
+				}
+			}
+		}
+	}
+}
+
+pub async fn serve(config: Json, state: State, data_dir: PathBuf) -> Result<()> {
+	let config: DiscordConfig = serde_json::from_value(config)?;
+	let subscriptions = Mutex::new(Subscriptions::load(data_dir.join(&config.subscriptions)).await?);
+	let poll = Duration::from_secs(config.poll_secs);
+	
+	let discord = Discord {
+		client: reqwest::Client::new(),
+		api_url: config.api_url.trim_end_matches('/').to_string(),
+		token: config.token,
+	};
+	
+	info!("Serving Discord bot in {} channels", config.channels.len());
+	
+	let polls = futures::future::join_all(config.channels.iter().map(|channel| poll_channel(&discord, &state, &subscriptions, channel, poll)));
+	tokio::join!(polls, send_loop(&discord, &state, &subscriptions));
+	
+	Ok(())
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("Discord error: {0}")]
+pub struct DiscordError(String);
@@ -0,0 +1,316 @@
+use std::fmt::Write;
+use chrono::{DateTime, Duration, Local, NaiveDateTime, TimeZone, Timelike};
+use serde::Deserialize;
+use regex::{Regex, RegexBuilder};
+use itertools::Itertools;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use tokio::sync::broadcast::error::RecvError;
+use anyhow::Result;
+use thiserror::Error;
+use tracing::{info, warn, error};
+
+use crate::utils::{Json, Map, escape_html};
+use crate::state::State;
+use crate::feeds::Entry;
+
+// Entries kept for the digest while emails can't be sent, oldest are dropped first
+const MAX_PENDING: usize = 1000;
+
+#[derive(Deserialize)]
+struct EmailConfig {
+	host: String,
+	port: Option<u16>,
+	#[serde(default)]
+	tls: Encryption,
+	username: Option<String>,
+	password: Option<String>,
+	from: String,
+	to: Vec<String>,
+	#[serde(default)]
+	digest: Digest,
+	// Local hour at which daily digest is sent
+	#[serde(rename="digestHour", default)]
+	digest_hour: u32,
+	// Regex, only entries of matching feeds are included in the digest
+	filter: Option<String>,
+	// Regex, entries of matching feeds are sent right away instead of waiting for the digest
+	immediate: Option<String>,
+}
+
+#[derive(Deserialize, Copy, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Encryption {
+	#[default]
+	StartTls,
+	Tls,
+	None,
+}
+
+#[derive(Deserialize, Copy, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Digest {
+	Hourly,
+	#[default]
+	Daily,
+	Never,
+}
+
+fn build_regex(regex: &str) -> Result<Regex> {
+	Ok(RegexBuilder::new(regex)
+	                .size_limit(1024 * 32)
+	                .dfa_size_limit(1024 * 32)
+	                .nest_limit(10)
+	                .case_insensitive(true)
+	                .build()?)
+}
+
+fn matches(regex: &Option<Regex>, entry: &Entry) -> bool {
+	regex.as_ref().is_some_and(|regex| entry.feed_name.as_ref().is_some_and(|name| regex.is_match(name)))
+}
+
+// Earlier of repeated times when clocks go back, first valid hour after the gap when they go forward
+fn resolve_local<Tz: TimeZone>(timezone: &Tz, time: NaiveDateTime) -> Option<DateTime<Tz>> {
+	(0..3).find_map(|hours| timezone.from_local_datetime(&(time + Duration::hours(hours))).earliest())
+}
+
+fn next_digest<Tz: TimeZone>(digest: Digest, hour: u32, now: DateTime<Tz>) -> Option<DateTime<Tz>> {
+	match digest {
+		Digest::Hourly => {
+			let past_hour = Duration::minutes(now.minute().into()) + Duration::seconds(now.second().into()) + Duration::nanoseconds(now.nanosecond().into());
+			Some(now - past_hour + Duration::hours(1))
+		},
+		Digest::Daily => {
+			let today = now.date_naive();
+			
+			(0..=2).filter_map(|days| (today + Duration::days(days)).and_hms_opt(hour, 0, 0))
+			       .filter_map(|time| resolve_local(&now.timezone(), time))
+			       .find(|time| *time > now)
+		},
+		Digest::Never => None,
+	}
+}
+
+// Removes the oldest entries over the limit and returns them
+fn drop_oldest(pending: &mut Vec<Entry>, limit: usize) -> Vec<Entry> {
+	let excess = pending.len().saturating_sub(limit);
+	pending.drain(..excess).collect()
+}
+
+fn render_text(feeds: &Map<Vec<&Entry>>) -> String {
+	let mut text = String::new();
+	
+	for (name, entries) in feeds {
+		let _ = writeln!(text, "{}\n{}", name, "=".repeat(name.chars().count()));
+		
+		for entry in entries {
+			let _ = writeln!(text, "* {}", entry.title);
+			if let Some(description) = &entry.description { let _ = writeln!(text, "  {}", description.lines().join("\n  ")); }
+			if let Some(link) = &entry.link { let _ = writeln!(text, "  {}", link); }
+		}
+		
+		text += "\n";
+	}
+	
+	text
+}
+
+fn render_html(feeds: &Map<Vec<&Entry>>) -> String {
+	let mut html = String::from("<!DOCTYPE html><html><body style=\"font-family: sans-serif\">");
+	
+	for (name, entries) in feeds {
+		let color = entries.iter().find_map(|entry| entry.color.as_ref()).map_or("inherit", |color| color.as_str());
+		let _ = write!(html, "<h2 style=\"border-left: 4px solid {}; padding-left: 8px\">{}</h2><ul>", escape_html(color), escape_html(name));
+		
+		for entry in entries {
+			html += "<li style=\"margin-bottom: 8px\">";
+			match &entry.link {
+				Some(link) => { let _ = write!(html, "<a href=\"{}\"><b>{}</b></a>", escape_html(link), escape_html(&entry.title)); },
+				None => { let _ = write!(html, "<b>{}</b>", escape_html(&entry.title)); },
+			}
+			if let Some(timestamp) = entry.timestamp {
+				let _ = write!(html, " <small>{}</small>", timestamp.with_timezone(&Local).format("%Y-%m-%d %H:%M"));
+			}
+			if let Some(description) = &entry.description {
+				let _ = write!(html, "<br>{}", escape_html(description).replace('\n', "<br>"));
+			}
+			html += "</li>";
+		}
+		
+		html += "</ul>";
+	}
+	
+	html += "</body></html>";
+	html
+}
+
+struct Mailer {
+	transport: AsyncSmtpTransport<Tokio1Executor>,
+	from: Mailbox,
+	to: Vec<Mailbox>,
+}
+
+impl Mailer {
+	fn new(config: &EmailConfig) -> Result<Self> {
+		let mut builder = match config.tls {
+			Encryption::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?,
+			Encryption::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?,
+			Encryption::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+		};
+		
+		if let Some(port) = config.port {
+			builder = builder.port(port);
+		}
+		
+		if let (Some(username), Some(password)) = (&config.username, &config.password) {
+			builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+		}
+		
+		Ok(Mailer {
+			transport: builder.build(),
+			from: config.from.parse()?,
+			to: config.to.iter().map(|to| to.parse()).collect::<Result<_, _>>()?,
+		})
+	}
+	
+	async fn send(&self, subject: &str, entries: &[Entry]) -> Result<()> {
+		let feeds: Map<Vec<&Entry>> = entries.iter()
+		                                     .sorted()
+		                                     .into_group_map_by(|entry| entry.feed_name.clone().unwrap_or_default())
+		                                     .into_iter()
+		                                     .collect();
+		
+		let mut message = Message::builder()
+		                          .from(self.from.clone())
+		                          .subject(subject);
+		
+		for to in self.to.iter() {
+			message = message.to(to.clone());
+		}
+		
+		let message = message.multipart(MultiPart::alternative_plain_html(render_text(&feeds), render_html(&feeds)))?;
+		self.transport.send(message).await?;
+		
+		Ok(())
+	}
+}
+
+pub async fn serve(config: Json, state: State) -> Result<()> {
+	let config: EmailConfig = serde_json::from_value(config)?;
+	if config.digest_hour >= 24 { Err(InvalidDigestHourError(config.digest_hour))? }
+	
+	let mailer = Mailer::new(&config)?;
+	let filter = config.filter.as_deref().map(build_regex).transpose()?;
+	let immediate = config.immediate.as_deref().map(build_regex).transpose()?;
+	
+	let mut receiver = state.new_entries.subscribe();
+	let mut pending: Vec<Entry> = vec![];
+	let mut next = next_digest(config.digest, config.digest_hour, Local::now());
+	
+	info!("Sending email to {}", config.to.join(", "));
+	
+	loop {
+		let sleep = async move {
+			match next {
+				Some(next) => tokio::time::sleep((next - Local::now()).to_std().unwrap_or_default()).await,
+				None => futures::future::pending().await,
+			}
+		};
+		
+		tokio::select! {
+			received = receiver.recv() => {
+				let entries = match received {
+					Ok(entries) => entries,
+					Err(RecvError::Lagged(skipped)) => { warn!(skipped, "Email skipped updates"); continue },
+					Err(RecvError::Closed) => return Ok(()),
+				};
+				
+				let (now, later): (Vec<Entry>, Vec<Entry>) = entries.iter()
+				                                                    .cloned()
+				                                                    .partition(|entry| matches(&immediate, entry));
+				
+				if !now.is_empty() {
+					let subject = match &now[..] {
+						[entry] => entry.title.clone(),
+						entries => format!("{} new notifications", entries.len()),
+					};
+					
+					if let Err(err) = mailer.send(&subject, &now).await {
+						error!(error = %err, "Unable to send email");
+					}
+				}
+				
+				if config.digest != Digest::Never {
+					pending.extend(later.into_iter().filter(|entry| filter.is_none() || matches(&filter, entry)));
+					
+					let dropped = drop_oldest(&mut pending, MAX_PENDING);
+					if !dropped.is_empty() {
+						let titles = dropped.iter().map(|entry| entry.title.as_str()).join(", ");
+						warn!(dropped = dropped.len(), titles, "Email digest is full, dropped oldest entries");
+					}
+				}
+			},
+			_ = sleep => {
+				next = next_digest(config.digest, config.digest_hour, Local::now());
+				if pending.is_empty() { continue }
+				
+				let subject = format!("Notifier digest: {} new entries ({})", pending.len(), Local::now().format("%Y-%m-%d %H:%M"));
+				match mailer.send(&subject, &pending).await {
+					Ok(()) => pending.clear(),
+					Err(err) => error!(error = %err, "Unable to send email digest"),
+				}
+			},
+		}
+	}
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("Invalid digestHour {0}, expected 0-23")]
+pub struct InvalidDigestHourError(u32);
+
+#[cfg(test)]
+mod tests {
+	use chrono::Offset;
+	use chrono_tz::Europe::Berlin;
+	use super::*;
+	
+	#[test]
+	fn drops_oldest_entries() {
+		let mut pending = (0..5).map(|i| Entry::new(&format!("Entry {}", i), &i.to_string())).collect::<Vec<_>>();
+		
+		let dropped = drop_oldest(&mut pending, 3);
+		assert_eq!(dropped.iter().map(|entry| entry.title.as_str()).collect::<Vec<_>>(), ["Entry 0", "Entry 1"]);
+		assert_eq!(pending.len(), 3);
+		assert_eq!(pending[0].title, "Entry 2");
+		
+		assert!(drop_oldest(&mut pending, 3).is_empty());
+	}
+	
+	#[test]
+	fn schedules_digests() {
+		let now = Local.with_ymd_and_hms(2024, 5, 1, 10, 30, 0).unwrap();
+		
+		assert_eq!(next_digest(Digest::Hourly, 0, now), Local.with_ymd_and_hms(2024, 5, 1, 11, 0, 0).single());
+		assert_eq!(next_digest(Digest::Daily, 18, now), Local.with_ymd_and_hms(2024, 5, 1, 18, 0, 0).single());
+		assert_eq!(next_digest(Digest::Daily, 8, now), Local.with_ymd_and_hms(2024, 5, 2, 8, 0, 0).single());
+		assert_eq!(next_digest(Digest::Never, 8, now), None);
+	}
+	
+	#[test]
+	fn schedules_digests_around_dst() {
+		let berlin = |d, h, m| Berlin.with_ymd_and_hms(2024, 3, d, h, m, 0).earliest().unwrap();
+		
+		// 02:00 doesn't exist on 31 March, digest is sent when the clock jumps to 03:00
+		assert_eq!(next_digest(Digest::Daily, 2, berlin(30, 23, 0)), Some(berlin(31, 3, 0)));
+		assert_eq!(next_digest(Digest::Daily, 2, berlin(31, 3, 30)), Some(Berlin.with_ymd_and_hms(2024, 4, 1, 2, 0, 0).unwrap()));
+		// Same local hour after the change, not 24 hours later
+		assert_eq!(next_digest(Digest::Daily, 8, berlin(30, 10, 0)), Some(berlin(31, 8, 0)));
+		assert_eq!(next_digest(Digest::Hourly, 0, berlin(31, 1, 30)), Some(berlin(31, 3, 0)));
+		
+		// 02:00 happens twice on 27 October, the first one is used
+		let repeated = Berlin.with_ymd_and_hms(2024, 10, 27, 2, 0, 0).earliest().unwrap();
+		assert_eq!(next_digest(Digest::Daily, 2, Berlin.with_ymd_and_hms(2024, 10, 26, 12, 0, 0).unwrap()), Some(repeated));
+		assert_eq!(repeated.offset().fix().local_minus_utc(), 2 * 3600);
+	}
+}
@@ -0,0 +1,419 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::json;
+use regex::{Regex, RegexBuilder};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use tokio::sync::broadcast::error::RecvError;
+use anyhow::Result;
+use thiserror::Error;
+use tracing::{info, warn, error};
+
+use crate::utils::{Json, Map, escape_html};
+use crate::state::State;
+use crate::feeds::Entry;
+
+#[derive(Deserialize)]
+struct MatrixConfig {
+	// eg. https://matrix.org
+	homeserver: String,
+	#[serde(rename="accessToken")]
+	access_token: String,
+	rooms: Vec<RoomConfig>,
+	// Answer !feeds, !errors and !read commands
+	#[serde(default = "default_commands")]
+	commands: bool,
+}
+
+fn default_commands() -> bool { true }
+
+#[derive(Deserialize)]
+struct RoomConfig {
+	// Room ID or alias, the bot joins it on start
+	room: String,
+	// Regex, only entries of matching feeds are posted
+	filter: Option<String>,
+}
+
+struct Room {
+	id: String,
+	filter: Option<Regex>,
+}
+
+impl Room {
+	fn matches(&self, entry: &Entry) -> bool {
+		self.filter.as_ref().is_none_or(|filter| entry.feed_name.as_ref().is_some_and(|name| filter.is_match(name)))
+	}
+}
+
+#[derive(Deserialize)]
+struct WhoAmI {
+	user_id: String,
+}
+
+#[derive(Deserialize)]
+struct Joined {
+	room_id: String,
+}
+
+#[derive(Deserialize)]
+struct Sync {
+	next_batch: String,
+	#[serde(default)]
+	rooms: SyncRooms,
+}
+
+#[derive(Deserialize, Default)]
+struct SyncRooms {
+	#[serde(default)]
+	join: Map<JoinedRoom>,
+}
+
+#[derive(Deserialize)]
+struct JoinedRoom {
+	#[serde(default)]
+	timeline: Timeline,
+}
+
+#[derive(Deserialize, Default)]
+struct Timeline {
+	#[serde(default)]
+	events: Vec<Event>,
+}
+
+#[derive(Deserialize)]
+struct Event {
+	#[serde(rename="type")]
+	kind: String,
+	sender: String,
+	#[serde(default)]
+	content: Json,
+}
+
+struct Message {
+	body: String,
+	html: String,
+}
+
+impl Message {
+	fn new() -> Self {
+		Message { body: String::new(), html: String::new() }
+	}
+	
+	fn line(&mut self, body: &str, html: &str) {
+		if !self.body.is_empty() {
+			self.body += "\n";
+			self.html += "<br>";
+		}
+		
+		self.body += body;
+		self.html += html;
+	}
+	
+	fn text(&mut self, text: &str) {
+		self.line(text, &escape_html(text));
+	}
+	
+	fn entry(&mut self, entry: &Entry) {
+		let feed_name = entry.feed_name.as_deref().unwrap_or_default();
+		
+		let mut body = format!("{}: {}", feed_name, entry.title);
+		let mut html = format!("<b>{}</b>: ", escape_html(feed_name));
+		match &entry.link {
+			Some(link) => {
+				let _ = write!(body, " {}", link);
+				let _ = write!(html, "<a href=\"{}\">{}</a>", escape_html(link), escape_html(&entry.title));
+			},
+			None => html += &escape_html(&entry.title),
+		}
+		
+		self.line(&body, &html);
+	}
+}
+
+struct Matrix {
+	client: reqwest::Client,
+	homeserver: String,
+	access_token: String,
+	txn_prefix: i64,
+	txn: AtomicU64,
+}
+
+impl Matrix {
+	fn url(&self, path: &str) -> String {
+		format!("{}/_matrix/client/v3{}", self.homeserver.trim_end_matches('/'), path)
+	}
+	
+	async fn call<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> Result<T> {
+		let response = request.bearer_auth(&self.access_token)
+		                      .send()
+		                      .await?;
+		
+		if !response.status().is_success() {
+			let status = response.status();
+			return Err(match response.json::<MatrixError>().await {
+				Ok(err) => err.into(),
+				Err(_) => anyhow::anyhow!("Matrix homeserver responded with {}", status),
+			});
+		}
+		
+		Ok(response.json().await?)
+	}
+	
+	async fn whoami(&self) -> Result<String> {
+		let whoami: WhoAmI = self.call(self.client.get(self.url("/account/whoami"))).await?;
+		Ok(whoami.user_id)
+	}
+	
+	// Resolves aliases to room IDs
+	async fn join(&self, room: &str) -> Result<String> {
+		let url = self.url(&format!("/join/{}", utf8_percent_encode(room, NON_ALPHANUMERIC)));
+		let joined: Joined = self.call(self.client.post(url).json(&json!({}))).await?;
+		Ok(joined.room_id)
+	}
+	
+	async fn send(&self, room_id: &str, message: &Message) -> Result<()> {
+		let txn = format!("{}.{}", self.txn_prefix, self.txn.fetch_add(1, Ordering::Relaxed));
+		let url = self.url(&format!("/rooms/{}/send/m.room.message/{}", utf8_percent_encode(room_id, NON_ALPHANUMERIC), txn));
+		
+		// Bots are expected to send notices, which other bots don't respond to
+		let body = json!({
+			"msgtype": "m.notice",
+			"body": message.body,
+			"format": "org.matrix.custom.html",
+			"formatted_body": message.html,
+		});
+		
+		let _: Json = self.call(self.client.put(url).json(&body)).await?;
+		
+		Ok(())
+	}
+	
+	async fn sync(&self, since: Option<&str>, timeout: Duration) -> Result<Sync> {
+		let filter = json!({
+			"room": { "timeline": { "types": ["m.room.message"] } },
+			"presence": { "types": [] },
+			"account_data": { "types": [] },
+		});
+		
+		let mut request = self.client.get(self.url("/sync"))
+		                             .query(&[("filter", filter.to_string()), ("timeout", timeout.as_millis().to_string())]);
+		
+		if let Some(since) = since {
+			request = request.query(&[("since", since)]);
+		}
+		
+		self.call(request).await
+	}
+}
+
+// Commands only see feeds matching the room's filter
+fn command_response(state: &State, room: &Room, command: &str) -> Option<Message> {
+	let feeds = state.feeds.load();
+	let feeds = feeds.filtered(room.filter.as_ref()).collect::<Vec<_>>();
+	let mut message = Message::new();
+	let (command, args) = command.trim().split_once(' ').unwrap_or((command.trim(), ""));
+	
+	match command {
+		"!feeds" => {
+			if feeds.is_empty() { message.text("No feeds") }
+			
+			for (name, feed) in feeds.iter() {
+				message.text(&format!("{}: {} status, {} notifications, {} errors", name, feed.status.len(), feed.notifications.len(), feed.errors.len()));
+			}
+		},
+		"!errors" => {
+			let errors = feeds.iter().flat_map(|(_, feed)| feed.errors.iter()).collect::<Vec<_>>();
+			if errors.is_empty() { message.text("No errors") }
+			
+			for entry in errors {
+				match &entry.description {
+					Some(description) => message.text(&format!("{}: {} ({})", entry.feed_name.as_deref().unwrap_or_default(), entry.title, description)),
+					None => message.entry(entry),
+				}
+			}
+		},
+		"!read" => {
+			let name = args.trim();
+			let Some((_, feed)) = feeds.iter().find(|(feed_name, _)| *feed_name == name) else {
+				message.text(&format!("Unknown feed \"{}\"", name));
+				return Some(message);
+			};
+			
+			if feed.status.is_empty() && feed.notifications.is_empty() { message.text("Nothing to read") }
+			
+			for entry in feed.status.iter().chain(feed.notifications.iter().take(10)) {
+				message.entry(entry);
+			}
+		},
+		"!help" => message.text("Commands: !feeds, !errors, !read <feed>"),
+		_ => return None,
+	}
+	
+	Some(message)
+}
+
+async fn sync_loop(matrix: &Matrix, state: &State, rooms: &[Room], user_id: &str) {
+	let mut since = None;
+	
+	loop {
+		// Initial sync doesn't wait, so commands sent before the bot started are skipped
+		let timeout = if since.is_some() { Duration::from_secs(30) } else { Duration::ZERO };
+		
+		let sync = match matrix.sync(since.as_deref(), timeout).await {
+			Ok(sync) => sync,
+			Err(err) => {
+				warn!(error = %err, "Matrix sync failed");
+				tokio::time::sleep(Duration::from_secs(5)).await;
+				continue
+			},
+		};
+		
+		if since.is_some() {
+			for (room_id, joined) in sync.rooms.join.iter() {
+				// Rooms the bot was invited to, but which aren't configured, are ignored
+				let Some(room) = rooms.iter().find(|room| &room.id == room_id) else { continue };
+				
+				for event in joined.timeline.events.iter() {
+					if event.kind != "m.room.message" || event.sender == user_id { continue }
+					if event.content["msgtype"] != "m.text" { continue }
+					
+					let Some(body) = event.content["body"].as_str() else { continue };
+					let Some(response) = command_response(state, room, body) else { continue };
+					
+					if let Err(err) = matrix.send(&room.id, &response).await {
+						warn!(error = %err, "Unable to respond to Matrix command");
+					}
+				}
+			}
+		}
+		
+		since = Some(sync.next_batch);
+	}
+}
+
+async fn post_loop(matrix: &Matrix, state: &State, rooms: &[Room]) {
+	let mut receiver = state.new_entries.subscribe();
+	
+	loop {
+		let entries = match receiver.recv().await {
+			Ok(entries) => entries,
+			Err(RecvError::Lagged(skipped)) => { warn!(skipped, "Matrix skipped updates"); continue },
+			Err(RecvError::Closed) => return,
+		};
+		
+		// One message per room and fetch, to stay within rate limits
+		for room in rooms {
+			let mut message = Message::new();
+			for entry in entries.iter().filter(|entry| room.matches(entry)) {
+				message.entry(entry);
+			}
+			
+			if message.body.is_empty() { continue }
+			
+			if let Err(err) = matrix.send(&room.id, &message).await {
+				error!(room = %room.id, error = %err, "Unable to post to Matrix room");
+			}
+		}
+	}
+}
+
+pub async fn serve(config: Json, state: State) -> Result<()> {
+	let config: MatrixConfig = serde_json::from_value(config)?;
+	
+	let matrix = Matrix {
+		client: reqwest::Client::new(),
+		homeserver: config.homeserver,
+		access_token: config.access_token,
+		txn_prefix: chrono::Utc::now().timestamp_millis(),
+		txn: AtomicU64::new(0),
+	};
+	
+	let user_id = matrix.whoami().await?;
+	
+	let mut rooms = vec![];
+	for room in config.rooms {
+		let filter = room.filter
+		                 .map(|filter| RegexBuilder::new(&filter)
+		                                            .size_limit(1024 * 32)
+		                                            .dfa_size_limit(1024 * 32)
+		                                            .nest_limit(10)
+		                                            .case_insensitive(true)
+		                                            .build())
+		                 .transpose()?;
+		
+		rooms.push(Room { id: matrix.join(&room.room).await?, filter });
+	}
+	
+	info!("Posting to {} Matrix rooms as {}", rooms.len(), user_id);
+	
+	if config.commands {
+		tokio::join!(post_loop(&matrix, &state, &rooms), sync_loop(&matrix, &state, &rooms, &user_id));
+	} else {
+		post_loop(&matrix, &state, &rooms).await;
+	}
+	
+	Ok(())
+}
+
+#[derive(Debug, Clone, Deserialize, Error)]
+#[error("Matrix error {errcode}: {error}")]
+pub struct MatrixError {
+	errcode: String,
+	#[serde(default)]
+	error: String,
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+	use std::sync::Arc;
+	use super::*;
+	use crate::feeds::{Feed, Feeds};
+	
+	fn state() -> State {
+		let state = State::new(Map::new(), PathBuf::new(), Duration::from_secs(60));
+		let mut feeds = Feeds::new();
+		
+		for name in &["news", "private"] {
+			let mut feed = Feed::new();
+			feed.notifications.push(Entry::new(&format!("{} entry", name), "1").feed_name(name));
+			feed.errors.push(Entry::new(&format!("{} error", name), "2").feed_name(name));
+			feeds.insert(name.to_string(), feed);
+		}
+		
+		state.feeds.store(Arc::new(feeds));
+		state
+	}
+	
+	fn room(filter: Option<&str>) -> Room {
+		Room { id: "!room".to_string(), filter: filter.map(|filter| Regex::new(filter).unwrap()) }
+	}
+	
+	#[test]
+	fn commands_follow_room_filter() {
+		let state = state();
+		let room = room(Some("^news$"));
+		
+		let feeds = command_response(&state, &room, "!feeds").unwrap().body;
+		assert!(feeds.contains("news") && !feeds.contains("private"));
+		
+		let errors = command_response(&state, &room, "!errors").unwrap().body;
+		assert!(errors.contains("news error") && !errors.contains("private error"));
+		
+		let read = command_response(&state, &room, "!read private").unwrap().body;
+		assert_eq!(read, "Unknown feed \"private\"");
+		
+		let read = command_response(&state, &room, "!read news").unwrap().body;
+		assert!(read.contains("news entry"));
+	}
+	
+	#[test]
+	fn commands_without_filter() {
+		let feeds = command_response(&state(), &room(None), "!feeds").unwrap().body;
+		assert!(feeds.contains("news") && feeds.contains("private"));
+		
+		assert!(command_response(&state(), &room(None), "hello").is_none());
+	}
+}
@@ -1,12 +1,24 @@
+use std::path::PathBuf;
 use serde::Deserialize;
 use futures::future;
+use tracing::{error, info_span, Instrument};
 use anyhow::Result;
 use thiserror::Error;
 
 mod web;
+mod webhook;
+mod email;
+mod mqtt;
+mod desktop;
+mod matrix;
+mod telegram;
+mod discord;
+mod subscriptions;
+mod socket;
 
 use crate::utils::{Map, Json};
 use crate::state::State;
+pub use web::local_instance;
 
 
 #[derive(Deserialize)]
@@ -14,36 +26,48 @@ struct AnyInterfaceConfig {
 	enabled: bool,
 }
 
-async fn serve_interface(name: String, config: Json, state: State) {
+async fn serve_interface(name: String, config: Json, state: State, data_dir: PathBuf) {
 	match serde_json::from_value(config.clone()) {
 		Ok(AnyInterfaceConfig{ enabled }) if !enabled => return,
-		Err(err) => return eprintln!("Unable to serve {} interface: {}", name, err.to_string()),
+		Err(err) => return error!(error = %err, "Unable to serve interface"),
 		_ => {},
 	}
 	
 	// Add new interfaces here
 	let result = match &*name {
-		"web" => web::serve(config, state).await,
+		"web" => web::serve(config, state, data_dir).await,
+		"webhook" => webhook::serve(config, state, data_dir).await,
+		"email" => email::serve(config, state).await,
+		"mqtt" => mqtt::serve(config, state).await,
+		"desktop" => desktop::serve(config, state).await,
+		"matrix" => matrix::serve(config, state).await,
+		"telegram" => telegram::serve(config, state, data_dir).await,
+		"discord" => discord::serve(config, state, data_dir).await,
+		"socket" => socket::serve(config, state, data_dir).await,
 		_ => Err(InterfaceNotFound.into()),
 	};
 	
 	if let Err(err) = result {
-		eprintln!("Unable to serve {} interface: {}", name, err.to_string());
+		error!(error = %err, "Unable to serve interface");
 	}
 }
 
 pub struct Interfaces {
 	configs: Map<Json>,
+	data_dir: PathBuf,
 }
 
 impl Interfaces {
-	pub fn new(configs: Map<Json>) -> Self {
-		Interfaces{ configs }
+	pub fn new(configs: Map<Json>, data_dir: PathBuf) -> Self {
+		Interfaces{ configs, data_dir }
 	}
 	
 	pub async fn serve(&self, state: State) -> Result<()> {
 		let providers = self.configs.iter()
-		                            .map(|(name, config)| tokio::spawn(serve_interface(name.clone(), config.clone(), state.clone())))
+		                            .map(|(name, config)| {
+			                            let span = info_span!("interface", interface = %name);
+			                            tokio::spawn(serve_interface(name.clone(), config.clone(), state.clone(), self.data_dir.clone()).instrument(span))
+		                            })
 		                            .collect::<Vec<_>>();
 		
 		future::try_join_all(providers).await?;
@@ -0,0 +1,248 @@
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use rumqttc::{AsyncClient, Event, Incoming, MqttOptions, QoS, TlsConfiguration, Transport};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use anyhow::Result;
+use tracing::{info, warn, error};
+
+use crate::utils::{Json, Map};
+use crate::state::State;
+use crate::feeds::{Entry, Feed};
+
+#[derive(Deserialize)]
+struct MqttConfig {
+	host: String,
+	port: Option<u16>,
+	#[serde(rename="clientId", default = "default_client_id")]
+	client_id: String,
+	username: Option<String>,
+	password: Option<String>,
+	#[serde(default)]
+	tls: bool,
+	// PEM file, system certificates are used if absent
+	#[serde(rename="caFile")]
+	ca_file: Option<String>,
+	#[serde(rename="topicPrefix", default = "default_topic_prefix")]
+	topic_prefix: String,
+	// Home Assistant MQTT discovery
+	#[serde(default)]
+	discovery: bool,
+	#[serde(rename="discoveryPrefix", default = "default_discovery_prefix")]
+	discovery_prefix: String,
+}
+
+fn default_client_id() -> String { "rust-notifier".to_string() }
+fn default_topic_prefix() -> String { "notifier".to_string() }
+fn default_discovery_prefix() -> String { "homeassistant".to_string() }
+
+#[derive(Serialize)]
+struct FeedState<'a> {
+	// New entries since the feed was last marked as read
+	unread: usize,
+	#[serde(rename="latestTitle")]
+	latest_title: Option<&'a str>,
+	status: usize,
+	notifications: usize,
+	errors: usize,
+}
+
+// Topic levels can't contain wildcards or separators
+fn topic_name(name: &str) -> String {
+	name.replace(['/', '+', '#'], "_")
+}
+
+// Home Assistant object ids are limited to [a-zA-Z0-9_-]
+fn object_id(name: &str) -> String {
+	name.chars()
+	    .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+	    .collect()
+}
+
+struct Publisher {
+	client: AsyncClient,
+	config: MqttConfig,
+	unread: Map<usize>,
+	discovered: Vec<String>,
+}
+
+impl Publisher {
+	fn feed_topic(&self, name: &str) -> String {
+		format!("{}/{}", self.config.topic_prefix, topic_name(name))
+	}
+	
+	async fn publish_state(&self, name: &str, feed: &Feed) -> Result<()> {
+		let state = FeedState {
+			unread: self.unread.get(name).copied().unwrap_or(0),
+			latest_title: feed.status.first().or(feed.notifications.first()).map(|entry| entry.title.as_str()),
+			status: feed.status.len(),
+			notifications: feed.notifications.len(),
+			errors: feed.errors.len(),
+		};
+		
+		self.client.publish(format!("{}/state", self.feed_topic(name)), QoS::AtLeastOnce, true, serde_json::to_vec(&state)?).await?;
+		
+		Ok(())
+	}
+	
+	async fn publish_discovery(&mut self, name: &str) -> Result<()> {
+		if self.discovered.iter().any(|discovered| discovered == name) { return Ok(()) }
+		
+		let state_topic = format!("{}/state", self.feed_topic(name));
+		let id = object_id(name);
+		let device = json!({
+			"identifiers": ["rust-notifier"],
+			"name": "Rust Notifier",
+			"sw_version": option_env!("CARGO_PKG_VERSION").unwrap_or("0.0.0"),
+		});
+		
+		let sensors = [
+			("sensor", "unread", json!({
+				"name": format!("{} unread", name),
+				"value_template": "{{ value_json.unread }}",
+				"json_attributes_topic": state_topic,
+				"icon": "mdi:bell",
+			})),
+			("sensor", "errors", json!({
+				"name": format!("{} errors", name),
+				"value_template": "{{ value_json.errors }}",
+				"icon": "mdi:alert",
+			})),
+			// Status entries are eg. live streams or active threads
+			("binary_sensor", "active", json!({
+				"name": format!("{} active", name),
+				"value_template": "{{ 'ON' if value_json.status > 0 else 'OFF' }}",
+			})),
+		];
+		
+		for (component, suffix, mut payload) in sensors {
+			payload["state_topic"] = json!(state_topic);
+			payload["unique_id"] = json!(format!("rust_notifier_{}_{}", id, suffix));
+			payload["device"] = device.clone();
+			
+			let topic = format!("{}/{}/rust_notifier/{}_{}/config", self.config.discovery_prefix, component, id, suffix);
+			self.client.publish(topic, QoS::AtLeastOnce, true, serde_json::to_vec(&payload)?).await?;
+		}
+		
+		self.discovered.push(name.to_string());
+		
+		Ok(())
+	}
+	
+	async fn update(&mut self, state: &State, entries: &[Entry]) -> Result<()> {
+		for entry in entries {
+			let Some(name) = &entry.feed_name else { continue };
+			
+			*self.unread.entry(name.clone()).or_default() += 1;
+			self.client.publish(self.feed_topic(name), QoS::AtLeastOnce, false, serde_json::to_vec(entry)?).await?;
+		}
+		
+		self.publish_all(state).await
+	}
+	
+	async fn publish_all(&mut self, state: &State) -> Result<()> {
+		let feeds = state.feeds.load();
+		
+		for (name, feed) in feeds.iter() {
+			if self.config.discovery {
+				self.publish_discovery(name).await?;
+			}
+			
+			self.publish_state(name, feed).await?;
+		}
+		
+		Ok(())
+	}
+	
+	// Publishing to {prefix}/{feed}/read resets unread counter
+	async fn mark_read(&mut self, state: &State, topic: &str) -> Result<()> {
+		let feeds = state.feeds.load();
+		let feed = feeds.iter().find(|(name, _)| format!("{}/read", self.feed_topic(name)) == topic);
+		
+		if let Some((name, feed)) = feed {
+			self.unread.insert(name.clone(), 0);
+			self.publish_state(name, feed).await?;
+		}
+		
+		Ok(())
+	}
+}
+
+pub async fn serve(config: Json, state: State) -> Result<()> {
+	let config: MqttConfig = serde_json::from_value(config)?;
+	
+	let port = config.port.unwrap_or(if config.tls { 8883 } else { 1883 });
+	let mut options = MqttOptions::new(&config.client_id, &config.host, port);
+	options.set_keep_alive(Duration::from_secs(30));
+	
+	if let (Some(username), Some(password)) = (&config.username, &config.password) {
+		options.set_credentials(username, password);
+	}
+	
+	if config.tls {
+		options.set_transport(match &config.ca_file {
+			Some(ca_file) => Transport::tls_with_config(TlsConfiguration::Simple {
+				ca: tokio::fs::read(ca_file).await?,
+				alpn: None,
+				client_auth: None,
+			}),
+			None => Transport::tls_with_default_config(),
+		});
+	}
+	
+	let (client, mut event_loop) = AsyncClient::new(options, 64);
+	let read_topic = format!("{}/+/read", config.topic_prefix);
+	let mut publisher = Publisher { client, config, unread: Map::new(), discovered: vec![] };
+	let mut receiver = state.new_entries.subscribe();
+	
+	// Event loop has to be polled independently, otherwise publishing blocks once its queue is full
+	let (incoming_sender, mut incoming) = mpsc::unbounded_channel();
+	tokio::spawn(async move {
+		loop {
+			match event_loop.poll().await {
+				Ok(Event::Incoming(packet)) => if incoming_sender.send(packet).is_err() { return },
+				Ok(_) => {},
+				Err(err) => {
+					warn!(error = %err, "MQTT connection error");
+					tokio::time::sleep(Duration::from_secs(5)).await;
+				},
+			}
+		}
+	});
+	
+	info!("Publishing to MQTT broker {}:{}", publisher.config.host, port);
+	
+	loop {
+		tokio::select! {
+			packet = incoming.recv() => match packet {
+				Some(Incoming::ConnAck(_)) => {
+					// Subscriptions and retained messages have to be restored after reconnecting
+					publisher.client.subscribe(&read_topic, QoS::AtLeastOnce).await?;
+					publisher.discovered.clear();
+					if let Err(err) = publisher.publish_all(&state).await {
+						error!(error = %err, "Unable to publish MQTT state");
+					}
+				},
+				Some(Incoming::Publish(publish)) => {
+					if let Err(err) = publisher.mark_read(&state, &publish.topic).await {
+						error!(error = %err, "Unable to publish MQTT state");
+					}
+				},
+				Some(_) => {},
+				None => return Ok(()),
+			},
+			received = receiver.recv() => {
+				let entries = match received {
+					Ok(entries) => entries,
+					Err(RecvError::Lagged(skipped)) => { warn!(skipped, "MQTT skipped updates"); continue },
+					Err(RecvError::Closed) => return Ok(()),
+				};
+				
+				if let Err(err) = publisher.update(&state, &entries).await {
+					error!(error = %err, "Unable to publish MQTT update");
+				}
+			},
+		}
+	}
+}
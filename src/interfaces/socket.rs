@@ -0,0 +1,149 @@
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use regex::Regex;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+use tokio::sync::broadcast::error::RecvError;
+use anyhow::Result;
+use tracing::{info, warn};
+
+use crate::utils::{Json, Map, IteratorEx, build_regex, bind_unix_socket};
+use crate::state::State;
+use crate::feeds::{Entry, Feed};
+
+#[derive(Deserialize)]
+struct SocketConfig {
+	// Relative to the data directory
+	#[serde(default = "default_path")]
+	path: String,
+	// Octal permissions of the socket file, only owner can connect by default
+	#[serde(default = "default_mode")]
+	mode: String,
+}
+
+fn default_path() -> String { "notifier.sock".to_string() }
+fn default_mode() -> String { "600".to_string() }
+
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase")]
+enum Request {
+	// Same as GET /feeds
+	Snapshot {
+		filter: Option<String>,
+		#[serde(default)]
+		flat: bool,
+	},
+	// New entries of matching feeds are sent until the connection is closed
+	Subscribe {
+		filter: Option<String>,
+	},
+	Refresh,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Response<'a> {
+	Snapshot { feeds: Map<&'a Feed> },
+	#[serde(rename = "flatSnapshot")]
+	FlatSnapshot { feed: Feed },
+	Subscribed,
+	Entry { entry: &'a Entry },
+	Refreshing,
+	Error { message: String },
+}
+
+async fn send(stream: &mut (impl AsyncWriteExt + Unpin), response: &Response<'_>) -> Result<()> {
+	let mut line = serde_json::to_vec(response)?;
+	line.push(b'\n');
+	stream.write_all(&line).await?;
+	Ok(())
+}
+
+async fn handle_request(writer: &mut (impl AsyncWriteExt + Unpin), state: &State, line: &str, subscription: &mut Option<Option<Regex>>) -> Result<()> {
+	let request = match serde_json::from_str(line) {
+		Ok(request) => request,
+		Err(err) => return send(writer, &Response::Error { message: err.to_string() }).await,
+	};
+	
+	match request {
+		Request::Snapshot { filter, flat } => {
+			let filter = match filter.as_deref().map(build_regex).transpose() {
+				Ok(filter) => filter,
+				Err(err) => return send(writer, &Response::Error { message: err.to_string() }).await,
+			};
+			
+			let feeds = state.feeds.load();
+			let feeds = feeds.filtered(filter.as_ref());
+			
+			if flat {
+				send(writer, &Response::FlatSnapshot { feed: feeds.map(|(_, feed)| feed).kmerge_feeds() }).await
+			} else {
+				send(writer, &Response::Snapshot { feeds: feeds.map(|(name, feed)| (name.clone(), feed)).collect() }).await
+			}
+		},
+		Request::Subscribe { filter } => match filter.as_deref().map(build_regex).transpose() {
+			Ok(filter) => {
+				*subscription = Some(filter);
+				send(writer, &Response::Subscribed).await
+			},
+			Err(err) => send(writer, &Response::Error { message: err.to_string() }).await,
+		},
+		Request::Refresh => {
+			state.refresh.notify_one();
+			send(writer, &Response::Refreshing).await
+		},
+	}
+}
+
+async fn handle_connection(stream: UnixStream, state: State) -> Result<()> {
+	let (reader, mut writer) = stream.into_split();
+	let mut lines = BufReader::new(reader).lines();
+	let mut receiver = state.new_entries.subscribe();
+	// Filter of the subscription, if client subscribed
+	let mut subscription: Option<Option<Regex>> = None;
+	
+	loop {
+		tokio::select! {
+			line = lines.next_line() => match line? {
+				Some(line) if line.trim().is_empty() => {},
+				Some(line) => handle_request(&mut writer, &state, &line, &mut subscription).await?,
+				None => return Ok(()),
+			},
+			received = receiver.recv() => {
+				let entries = match received {
+					Ok(entries) => entries,
+					Err(RecvError::Lagged(_)) => continue,
+					Err(RecvError::Closed) => return Ok(()),
+				};
+				
+				let Some(filter) = &subscription else { continue };
+				
+				for entry in entries.iter() {
+					if filter.as_ref().is_none_or(|filter| entry.feed_name.as_ref().is_some_and(|name| filter.is_match(name))) {
+						send(&mut writer, &Response::Entry { entry }).await?;
+					}
+				}
+			},
+		}
+	}
+}
+
+pub async fn serve(config: Json, state: State, data_dir: PathBuf) -> Result<()> {
+	let config: SocketConfig = serde_json::from_value(config)?;
+	let path = data_dir.join(&config.path);
+	let mode = u32::from_str_radix(&config.mode, 8)?;
+	let listener = bind_unix_socket(&path, mode).await?;
+	
+	info!("Listening on socket {}", path.to_string_lossy());
+	
+	loop {
+		let (stream, _) = listener.accept().await?;
+		let state = state.clone();
+		
+		tokio::spawn(async move {
+			if let Err(err) = handle_connection(stream, state).await {
+				warn!(error = %err, "Socket connection error");
+			}
+		});
+	}
+}
@@ -0,0 +1,135 @@
+use std::path::PathBuf;
+use regex::{Regex, RegexBuilder};
+use itertools::Itertools;
+use anyhow::Result;
+use tracing::warn;
+
+use crate::utils::{Map, load_data, save_data};
+use crate::state::State;
+use crate::feeds::Entry;
+
+struct Subscription {
+	pattern: String,
+	regex: Regex,
+}
+
+impl Subscription {
+	// Patterns have to match whole feed name, so plain names don't match other feeds
+	fn new(pattern: &str) -> Result<Self> {
+		let regex = RegexBuilder::new(&format!("^(?:{})$", pattern))
+		                         .size_limit(1024 * 32)
+		                         .dfa_size_limit(1024 * 32)
+		                         .nest_limit(10)
+		                         .case_insensitive(true)
+		                         .build()?;
+		
+		Ok(Subscription { pattern: pattern.to_string(), regex })
+	}
+}
+
+// Per-chat feed subscriptions of chat bots, persisted in the data directory
+pub struct Subscriptions {
+	path: PathBuf,
+	chats: Map<Vec<Subscription>>,
+}
+
+impl Subscriptions {
+	pub async fn load(path: PathBuf) -> Result<Self> {
+		let patterns: Map<Vec<String>> = load_data(&path).await?;
+		let mut chats = Map::new();
+		
+		for (chat, patterns) in patterns {
+			let subscriptions = patterns.iter()
+			                            .filter_map(|pattern| match Subscription::new(pattern) {
+			                                Ok(subscription) => Some(subscription),
+			                                Err(err) => { warn!(%pattern, %chat, error = %err, "Ignoring invalid subscription"); None },
+			                            })
+			                            .collect();
+			
+			chats.insert(chat, subscriptions);
+		}
+		
+		Ok(Subscriptions { path, chats })
+	}
+	
+	async fn save(&self) -> Result<()> {
+		let patterns: Map<Vec<&str>> = self.chats.iter()
+		                                         .filter(|(_, subscriptions)| !subscriptions.is_empty())
+		                                         .map(|(chat, subscriptions)| (chat.clone(), subscriptions.iter().map(|subscription| subscription.pattern.as_str()).collect()))
+		                                         .collect();
+		
+		save_data(&self.path, &patterns).await
+	}
+	
+	pub fn is_subscribed(&self, chat: &str, entry: &Entry) -> bool {
+		let Some(feed_name) = &entry.feed_name else { return false };
+		
+		self.chats.get(chat).is_some_and(|subscriptions| subscriptions.iter().any(|subscription| subscription.regex.is_match(feed_name)))
+	}
+	
+	// Chats subscribed to at least one of the entries
+	pub fn chats<'a>(&'a self, entries: &'a [Entry]) -> impl Iterator<Item = (&'a str, Vec<&'a Entry>)> + 'a {
+		self.chats.keys()
+		          .map(move |chat| (chat.as_str(), entries.iter().filter(|entry| self.is_subscribed(chat, entry)).collect::<Vec<_>>()))
+		          .filter(|(_, entries)| !entries.is_empty())
+	}
+	
+	// Handles subscription commands (without prefix), returns response or None if command is unknown
+	pub async fn command(&mut self, state: &State, chat: &str, prefix: &str, text: &str) -> Result<Option<String>> {
+		let (command, pattern) = text.trim().split_once(char::is_whitespace).unwrap_or((text.trim(), ""));
+		let pattern = pattern.trim();
+		let feeds = state.feeds.load();
+		
+		Ok(Some(match command {
+			"subscribe" if !pattern.is_empty() => {
+				let subscription = match Subscription::new(pattern) {
+					Ok(subscription) => subscription,
+					Err(err) => return Ok(Some(format!("Invalid pattern: {}", err))),
+				};
+				
+				let subscriptions = self.chats.entry(chat.to_string()).or_default();
+				if subscriptions.iter().any(|existing| existing.pattern == pattern) {
+					return Ok(Some(format!("Already subscribed to {}", pattern)));
+				}
+				
+				let matching = feeds.keys().filter(|name| subscription.regex.is_match(name)).join(", ");
+				subscriptions.push(subscription);
+				self.save().await?;
+				
+				if matching.is_empty() {
+					format!("Subscribed to {}, no feeds match it currently", pattern)
+				} else {
+					format!("Subscribed to {} ({})", pattern, matching)
+				}
+			},
+			"unsubscribe" if !pattern.is_empty() => {
+				let Some(subscriptions) = self.chats.get_mut(chat) else { return Ok(Some(format!("Not subscribed to {}", pattern))) };
+				let count = subscriptions.len();
+				subscriptions.retain(|subscription| subscription.pattern != pattern);
+				
+				if subscriptions.len() == count {
+					format!("Not subscribed to {}", pattern)
+				} else {
+					self.save().await?;
+					format!("Unsubscribed from {}", pattern)
+				}
+			},
+			"subscriptions" => match self.chats.get(chat) {
+				Some(subscriptions) if !subscriptions.is_empty() => format!("Subscriptions: {}", subscriptions.iter().map(|subscription| &subscription.pattern).join(", ")),
+				_ => "No subscriptions".to_string(),
+			},
+			"feeds" => match feeds.keys().join(", ") {
+				names if names.is_empty() => "No feeds".to_string(),
+				names => format!("Feeds: {}", names),
+			},
+			"start" | "help" | "subscribe" | "unsubscribe" => format!(
+				"{p}subscribe <feed or regex> - receive new entries of matching feeds\n\
+				 {p}unsubscribe <feed or regex> - stop receiving entries\n\
+				 {p}subscriptions - list subscriptions\n\
+				 {p}feeds - list available feeds",
+				p = prefix,
+			),
+			_ => return Ok(None),
+		}))
+	}
+}
@@ -0,0 +1,188 @@
+use std::path::PathBuf;
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::json;
+use tokio::sync::Mutex;
+use tokio::sync::broadcast::error::RecvError;
+use anyhow::Result;
+use thiserror::Error;
+use tracing::{info, warn, error};
+
+use crate::utils::{Json, escape_html};
+use crate::state::State;
+use crate::feeds::Entry;
+use super::subscriptions::Subscriptions;
+
+#[derive(Deserialize)]
+struct TelegramConfig {
+	token: String,
+	#[serde(rename="apiUrl", default = "default_api_url")]
+	api_url: String,
+	// Relative to the data directory
+	#[serde(default = "default_subscriptions")]
+	subscriptions: String,
+}
+
+fn default_api_url() -> String { "https://api.telegram.org".to_string() }
+fn default_subscriptions() -> String { "telegram-subscriptions.json".to_string() }
+
+#[derive(Deserialize)]
+struct Response<T> {
+	ok: bool,
+	result: Option<T>,
+	description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Update {
+	update_id: i64,
+	message: Option<Message>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+	chat: Chat,
+	text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Chat {
+	id: i64,
+}
+
+struct Telegram {
+	client: reqwest::Client,
+	url: String,
+}
+
+impl Telegram {
+	async fn call<T: DeserializeOwned>(&self, method: &str, body: Json) -> Result<T> {
+		let response: Response<T> = self.client.post(format!("{}/{}", self.url, method))
+		                                       .json(&body)
+		                                       .send()
+		                                       .await?
+		                                       .json()
+		                                       .await?;
+		
+		match response {
+			Response { ok: true, result: Some(result), .. } => Ok(result),
+			Response { description, .. } => Err(TelegramError(description.unwrap_or_default()).into()),
+		}
+	}
+	
+	async fn send_text(&self, chat: &str, text: &str) -> Result<()> {
+		let _: Json = self.call("sendMessage", json!({ "chat_id": chat, "text": text })).await?;
+		Ok(())
+	}
+	
+	async fn send_entry(&self, chat: &str, entry: &Entry) -> Result<()> {
+		let mut text = format!("<b>{}</b>\n", escape_html(entry.feed_name.as_deref().unwrap_or_default()));
+		match &entry.link {
+			Some(link) => text += &format!("<a href=\"{}\">{}</a>", escape_html(link), escape_html(&entry.title)),
+			None => text += &escape_html(&entry.title),
+		}
+		if let Some(description) = &entry.description {
+			// Captions are limited to 1024 characters
+			let description: String = description.chars().take(500).collect();
+			text += &format!("\n{}", escape_html(&description));
+		}
+		
+		if let Some(image_url) = &entry.image_url {
+			let photo = self.call::<Json>("sendPhoto", json!({ "chat_id": chat, "photo": image_url, "caption": text, "parse_mode": "HTML" })).await;
+			
+			match photo {
+				Ok(_) => return Ok(()),
+				// Telegram refuses some images, send the entry without it
+				Err(err) => warn!(url = %image_url, error = %err, "Unable to send Telegram photo"),
+			}
+		}
+		
+		let _: Json = self.call("sendMessage", json!({ "chat_id": chat, "text": text, "parse_mode": "HTML" })).await?;
+		Ok(())
+	}
+}
+
+async fn poll_loop(telegram: &Telegram, state: &State, subscriptions: &Mutex<Subscriptions>) {
+	let mut offset = 0;
+	
+	loop {
+		let updates: Vec<Update> = match telegram.call("getUpdates", json!({ "offset": offset, "timeout": 30, "allowed_updates": ["message"] })).await {
+			Ok(updates) => updates,
+			Err(err) => {
+				warn!(error = %err, "Unable to get Telegram updates");
+				tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+				continue
+			},
+		};
+		
+		for update in updates {
+			offset = offset.max(update.update_id + 1);
+			
+			let Some(Message { chat, text: Some(text) }) = update.message else { continue };
+			let Some(command) = text.strip_prefix('/') else { continue };
+			// Commands in groups are suffixed with bot's name, eg. /subscribe@notifier_bot
+			let command = match command.split_once(char::is_whitespace) {
+				Some((name, args)) => format!("{} {}", name.split('@').next().unwrap_or_default(), args),
+				None => command.split('@').next().unwrap_or_default().to_string(),
+			};
+			
+			let chat = chat.id.to_string();
+			let response = subscriptions.lock().await.command(state, &chat, "/", &command).await;
+			
+			let result = match response {
+				Ok(Some(response)) => telegram.send_text(&chat, &response).await,
+				Ok(None) => Ok(()),
+				Err(err) => Err(err),
+			};
+			
+			if let Err(err) = result {
+				warn!(error = %err, "Unable to handle Telegram command");
+			}
+		}
+	}
+}
+
+async fn send_loop(telegram: &Telegram, state: &State, subscriptions: &Mutex<Subscriptions>) {
+	let mut receiver = state.new_entries.subscribe();
+	
+	loop {
+		let entries = match receiver.recv().await {
+			Ok(entries) => entries,
+			Err(RecvError::Lagged(skipped)) => { warn!(skipped, "Telegram skipped updates"); continue },
+			Err(RecvError::Closed) => return,
+		};
+		
+		let deliveries = subscriptions.lock()
+		                              .await
+		                              .chats(&entries)
+		                              .map(|(chat, entries)| (chat.to_string(), entries.into_iter().cloned().collect::<Vec<_>>()))
+		                              .collect::<Vec<_>>();
+		
+		for (chat, entries) in deliveries {
+			for entry in entries {
+				if let Err(err) = telegram.send_entry(&chat, &entry).await {
+					error!(%chat, error = %err, "Unable to send Telegram message");
+				}
+			}
+		}
+	}
+}
+
+pub async fn serve(config: Json, state: State, data_dir: PathBuf) -> Result<()> {
+	let config: TelegramConfig = serde_json::from_value(config)?;
+	let subscriptions = Mutex::new(Subscriptions::load(data_dir.join(&config.subscriptions)).await?);
+	
+	let telegram = Telegram {
+		client: reqwest::Client::new(),
+		url: format!("{}/bot{}", config.api_url.trim_end_matches('/'), config.token),
+	};
+	
+	info!("Serving Telegram bot");
+	
+	tokio::join!(poll_loop(&telegram, &state, &subscriptions), send_loop(&telegram, &state, &subscriptions));
+	
+	Ok(())
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("Telegram error: {0}")]
+pub struct TelegramError(String);
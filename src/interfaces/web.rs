@@ -1,32 +1,153 @@
-use std::collections::BTreeMap;
 use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 use warp::{Filter, Rejection, Reply, reply, reject};
 use warp::http::StatusCode;
 use warp::reject::Reject;
-use regex::RegexBuilder;
-use futures::future;
-use rss::{ChannelBuilder, ItemBuilder, CategoryBuilder, GuidBuilder};
-use rss::extension::{Extension, ExtensionBuilder};
+use warp::filters::body::BodyDeserializeError;
+use futures::{future, FutureExt};
+use futures::future::join_all;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::UnixListenerStream;
+use thiserror::Error;
+use utoipa::ToSchema;
+use percent_encoding::percent_decode_str;
 use anyhow::Result;
+use tracing::{info, error};
 
-use crate::utils::{Json, Map, IteratorEx};
+use crate::utils::{Json, build_regex, bind_unix_socket};
 use crate::state::State;
+use crate::metrics;
 use crate::feeds::Feed;
 
+mod api;
+mod auth;
+use auth::{Access, TokenConfig, UnauthorizedReject, ForbiddenReject, authenticate};
+mod tls;
+use tls::Tls;
+mod formats;
+use formats::{Channel, generate_rss, generate_atom, generate_json_feed};
+mod query;
+use query::FetchQuery;
+mod cache;
+use cache::{CacheHeaders, NotModifiedReject, conditional};
+mod ws;
+mod health;
+
+const DASHBOARD: &str = include_str!("web/dashboard.html");
+
 #[derive(Deserialize)]
 struct WebConfig {
 	rest: bool,
 	rss: bool,
+	// Pushes updates after every fetch on /ws
 	websocket: bool,
 	port: u16,
+	// Addresses, or address:port pairs, to listen on. All IPv4 interfaces by default
+	bind: Option<Bind>,
+	// Certificate chain and private key in PEM format, reloaded when changed
+	#[serde(rename="tlsCert")]
+	tls_cert: Option<String>,
+	#[serde(rename="tlsKey")]
+	tls_key: Option<String>,
+	// Unix socket to listen on, relative to the data directory
+	socket: Option<String>,
+	// Octal permissions of the socket file, only owner can connect by default
+	#[serde(rename="socketMode", default = "default_socket_mode")]
+	socket_mode: String,
+	// Without any tokens everything can be read without authentication, but feeds can't be changed
+	#[serde(default)]
+	tokens: Vec<TokenConfig>,
+	// Maximum number of items in RSS, Atom and JSON Feed
+	#[serde(rename="feedItems", default = "default_feed_items")]
+	feed_items: usize,
+	// Base of links to feeds, eg. https://example.com/notifier. Taken from Host header if missing
+	#[serde(rename="publicUrl")]
+	public_url: Option<String>,
+}
+
+fn default_feed_items() -> usize { 50 }
+fn default_socket_mode() -> String { "600".to_string() }
+
+// How CLI commands reach the running instance
+pub struct LocalInstance {
+	pub url: String,
+	pub token: Option<String>,
+}
+
+// First bound address, with loopback in place of unspecified addresses (eg. 0.0.0.0)
+pub fn local_instance(config: Json) -> Result<LocalInstance> {
+	let config: WebConfig = serde_json::from_value(config)?;
+	
+	let mut address = match &config.bind {
+		Some(bind) => bind.addresses(config.port)?.into_iter().next().ok_or(NoAddressError)?,
+		None => SocketAddr::from(([127, 0, 0, 1], config.port)),
+	};
+	
+	match address.ip() {
+		IpAddr::V4(ip) if ip.is_unspecified() => address.set_ip(IpAddr::from([127, 0, 0, 1])),
+		IpAddr::V6(ip) if ip.is_unspecified() => address.set_ip(IpAddr::from([0, 0, 0, 0, 0, 0, 0, 1])),
+		_ => {},
+	}
+	
+	let scheme = if config.tls_cert.is_some() && config.tls_key.is_some() { "https" } else { "http" };
+	
+	Ok(LocalInstance {
+		url: format!("{}://{}", scheme, address),
+		token: config.tokens.iter().find_map(TokenConfig::read_token).map(str::to_string),
+	})
+}
+
+#[derive(Clone)]
+struct FeedOptions {
+	// RSS, Atom and JSON Feed output is enabled
+	rss: bool,
+	limit: usize,
+	public_url: Option<String>,
+	https: bool,
+}
+
+impl FeedOptions {
+	fn channel(&self, state: &State, title: String, link: Option<String>) -> Channel {
+		Channel {
+			title,
+			link,
+			updated: state.feeds.load().created,
+			ttl: state.fetch_interval.as_secs().div_ceil(60).max(1),
+			limit: self.limit,
+		}
+	}
+	
+	fn base_url(&self, host: Option<String>) -> Option<String> {
+		self.public_url
+		    .clone()
+		    .or_else(|| host.map(|host| format!("{}://{}", if self.https { "https" } else { "http" }, host)))
+		    .map(|url| url.trim_end_matches('/').to_string())
+	}
 }
 
 #[derive(Deserialize)]
-struct FetchQuery {
-	filter: Option<String>,
-	flat: Option<bool>,
-	format: Option<Format>,
+#[serde(untagged)]
+enum Bind {
+	One(String),
+	Many(Vec<String>),
+}
+
+impl Bind {
+	fn addresses(&self, port: u16) -> Result<Vec<SocketAddr>, InvalidBindError> {
+		let addresses = match self {
+			Bind::One(address) => std::slice::from_ref(address),
+			Bind::Many(addresses) => addresses.as_slice(),
+		};
+		
+		addresses.iter()
+		         .map(|address| address.parse::<SocketAddr>()
+		                               .or_else(|_| address.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, port)))
+		                               .map_err(|_| InvalidBindError(address.clone())))
+		         .collect()
+	}
 }
 
 #[derive(Deserialize)]
@@ -34,134 +155,319 @@ struct FetchQuery {
 enum Format {
 	JSON,
 	RSS,
+	Atom,
+	// JSON Feed 1.1
+	JsonFeed,
 }
 
-pub async fn serve(config: Json, state: State) -> Result<()> {
+pub async fn serve(config: Json, state: State, data_dir: PathBuf) -> Result<()> {
 	let config: WebConfig = serde_json::from_value(config)?;
+	auth::check_tokens(&config.tokens)?;
+	let auth = authenticate(Arc::new(config.tokens));
+	
+	let options = FeedOptions {
+		rss: config.rss,
+		limit: config.feed_items,
+		public_url: config.public_url.clone(),
+		https: config.tls_cert.is_some(),
+	};
+	
+	let fetch = feeds_get(state.clone(), auth.clone(), options.clone());
+	let feed_file = feed_file_get(state.clone(), auth.clone(), options);
+	let push = enabled(config.websocket).and(ws::route(state.clone(), auth.clone()));
+	let health = health::routes(state.clone(), auth.clone());
+	let api = enabled(config.rest).and(api::routes(state.clone(), auth));
+	
+	// Tokens are sent in headers, so browsers can't attach them to requests from other sites by themselves
+	let cors = warp::cors()
+	                .allow_any_origin()
+	                .allow_headers(["authorization", "content-type"])
+	                .allow_methods(["GET", "POST", "PUT", "DELETE"])
+	                .expose_headers(["x-next-cursor", "etag"]);
+	
+	let routes = dashboard().or(fetch).or(feed_file).or(push).or(health).or(api).recover(handle_rejection).with(cors);
+	
+	// warp compresses every response passed through, so it's chosen by Accept-Encoding first
+	let routes = encoding("br").and(routes.clone()).with(warp::compression::brotli())
+	                           .or(encoding("gzip").and(routes.clone()).with(warp::compression::gzip()))
+	                           .or(routes)
+	                           .with(reply::with::header("Vary", "Accept-Encoding, Authorization"))
+	                           .with(warp::log::custom(log_request));
 	
-	let fetch = feeds_get(state.clone());
+	let tls = match (config.tls_cert, config.tls_key) {
+		(Some(cert), Some(key)) => Some(Tls::load(cert.into(), key.into()).await?),
+		(None, None) => None,
+		_ => return Err(IncompleteTlsError.into()),
+	};
 	
-	let routes = fetch.recover(handle_rejection);
+	let addresses = match &config.bind {
+		Some(bind) => bind.addresses(config.port)?,
+		None => vec![SocketAddr::from(([0, 0, 0, 0], config.port))],
+	};
 	
-	println!("Serving web on port {}", config.port);
-	warp::serve(routes)
-	     .run(([0, 0, 0, 0], config.port))
-	     .await;
+	let mut servers = vec![];
+	
+	for address in addresses {
+		if let Some(tls) = &tls {
+			let listener = TcpListener::bind(address).await?;
+			info!("Serving web on https://{}", address);
+			servers.push(warp::serve(routes.clone()).run_incoming(tls.incoming(listener)).boxed());
+		} else {
+			let (address, server) = warp::serve(routes.clone()).try_bind_ephemeral(address)?;
+			info!("Serving web on http://{}", address);
+			servers.push(server.boxed());
+		}
+	}
+	
+	if let Some(path) = config.socket {
+		let path = data_dir.join(path);
+		let mode = u32::from_str_radix(&config.socket_mode, 8)?;
+		let listener = bind_unix_socket(&path, mode).await?;
+		info!("Serving web on socket {}", path.to_string_lossy());
+		servers.push(warp::serve(routes.clone()).run_incoming(UnixListenerStream::new(listener)).boxed());
+	}
+	
+	if let Some(tls) = &tls {
+		servers.push(tls.reload_loop().boxed());
+	}
+	
+	join_all(servers).await;
 	
 	Ok(())
 }
 
+// Rejects requests to parts of the interface disabled in config
+fn enabled(enabled: bool) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+	warp::any()
+	     .and_then(move || future::ready(if enabled { Ok(()) } else { Err(reject::not_found()) }))
+	     .untuple_one()
+}
+
+// GET /, static page which loads everything through the other routes
+fn dashboard() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::get()
+	     .and(warp::path::end())
+	     .map(|| reply::html(DASHBOARD))
+}
 
 // GET /feeds?filter=my-feed&flat=true
-fn feeds_get(state: State) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+fn feeds_get(state: State, auth: impl Filter<Extract = (Access,), Error = Rejection> + Clone + Send, options: FeedOptions) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
 	warp::get()
 	     .and(warp::path("feeds"))
+	     .and(warp::path::end())
+	     .and(auth)
 	     .and(warp::query())
-	     .and_then(move |query: FetchQuery| future::ready::<Result<Box<dyn Reply>, Rejection>>(try {
-		     let filter = query.filter.map(|filter| RegexBuilder::new(&filter)
-		                                                         .size_limit(1024 * 32)
-		                                                         .dfa_size_limit(1024 * 32)
-		                                                         .nest_limit(10)
-		                                                         .case_insensitive(true)
-		                                                         .build())
+	     .and(conditional(state.clone()))
+	     .and_then(move |access: Access, query: FetchQuery, cache: CacheHeaders| future::ready::<Result<Box<dyn Reply>, Rejection>>(try {
+		     access.require_read()?;
+		     
+		     let filter = query.filter
+		                       .as_deref()
+		                       .map(build_regex)
 		                       .transpose()
 		                       .map_err(RegexpReject)
 		                       .map_err(reject::custom)?;
 		     
+		     if !options.rss && matches!(query.format, Some(Format::RSS | Format::Atom | Format::JsonFeed)) {
+			     Err(reject::custom(BadRequestReject("RSS, Atom and JSON Feed formats are disabled".to_string())))?
+		     }
+		     
+		     cache.check()?;
+		     
 		     let feeds = state.feeds.load();
-		     let feeds = feeds.iter()
-		                      .filter(|(name, _)| filter.as_ref().map_or(true, |reg| reg.is_match(name)))
-		                      .map(|(name, feed)| (name.clone(), feed));
+		     let page = query.select(feeds.filtered(filter.as_ref()).filter(|(name, _)| access.can_read(name)));
+		     
+		     let flat = query.flat.unwrap_or(false);
+		     let channel = options.channel(&state, feed_title(page.names(), flat), None);
+		     
+		     let reply = match query.format {
+			     Some(Format::RSS) => Box::new(generate_rss(&channel, page.feed())) as Box<dyn Reply>,
+			     Some(Format::Atom) => Box::new(generate_atom(&channel, page.feed())) as Box<dyn Reply>,
+			     Some(Format::JsonFeed) => Box::new(generate_json_feed(&channel, page.feed())) as Box<dyn Reply>,
+			     _ if flat => Box::new(reply::json(&FlatPage { feed: page.feed(), next: page.next.clone() })) as Box<dyn Reply>,
+			     _ => Box::new(reply::json(&page.feeds())) as Box<dyn Reply>,
+		     };
+		     
+		     // Map of feeds has no place for the cursor, so it's sent in a header for every format
+		     match page.next {
+			     Some(next) => Box::new(cache.apply(reply::with_header(reply, "X-Next-Cursor", next))) as Box<dyn Reply>,
+			     None => Box::new(cache.apply(reply)) as Box<dyn Reply>,
+		     }
+	     }))
+}
+
+// GET /feeds/my-feed.rss or /feeds/my-feed.atom, single feed for feed readers
+fn feed_file_get(state: State, auth: impl Filter<Extract = (Access,), Error = Rejection> + Clone + Send, options: FeedOptions) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+	warp::get()
+	     .and(warp::path!("feeds" / String))
+	     .and(auth)
+	     .and(warp::header::optional::<String>("host"))
+	     .and(conditional(state.clone()))
+	     .and_then(move |file: String, access: Access, host: Option<String>, cache: CacheHeaders| future::ready::<Result<Box<dyn Reply>, Rejection>>(try {
+		     let (name, format) = match file.rsplit_once('.') {
+			     Some((name, "rss")) => Some((decode(name), Format::RSS)),
+			     Some((name, "atom")) => Some((decode(name), Format::Atom)),
+			     _ => None,
+		     }.ok_or_else(|| reject::custom(NotFoundReject(format!("Unknown feed file {}, expected .rss or .atom", file))))?;
+		     
+		     if !options.rss { Err(reject::custom(NotFoundReject("RSS and Atom feed files are disabled".to_string())))? }
+		     
+		     if !access.can_read(&name) { Err(feed_not_found(&name))? }
 		     
+		     // Feeds which weren't fetched yet are empty
+		     let feed = state.feeds
+		                     .load()
+		                     .get(&name)
+		                     .cloned()
+		                     .or_else(|| state.feed_entries.load().contains_key(&name).then(Feed::new))
+		                     .ok_or_else(|| feed_not_found(&name))?;
 		     
-		     if let Some(Format::RSS) = query.format {
-			     Box::new(generate_rss(feeds.map(|(_, feed)| feed).kmerge_feeds())) as Box<dyn Reply>
-		     } else if query.flat.unwrap_or(false) {
-			     Box::new(reply::json(&feeds.map(|(_, feed)| feed).kmerge_feeds())) as Box<dyn Reply>
-		     } else {
-			     Box::new(reply::json(&feeds.collect::<Map<&Feed>>())) as Box<dyn Reply>
+		     cache.check()?;
+		     
+		     let link = options.base_url(host).map(|base| format!("{}/feeds/{}", base, file));
+		     let channel = options.channel(&state, name, link);
+		     
+		     match format {
+			     Format::RSS => Box::new(cache.apply(generate_rss(&channel, feed))) as Box<dyn Reply>,
+			     _ => Box::new(cache.apply(generate_atom(&channel, feed))) as Box<dyn Reply>,
 		     }
 	     }))
-	     .with(warp::cors().allow_any_origin()).with(warp::log("cors test"))
-}
-
-fn map<T>(key: &str, value: T) -> BTreeMap<String, T> {
-	let mut map = BTreeMap::new();
-	map.insert(key.to_string(), value);
-	map
-}
-
-fn generate_extension(value: Json, name: String) -> Extension {
-	let mut builder = ExtensionBuilder::default();
-	builder.name(name.to_string());
-	
-	match value {
-		Json::Null => &mut builder,
-		Json::Bool(value) => builder.value(value.to_string()),
-		Json::Number(value) => builder.value(value.to_string()),
-		Json::String(value) => builder.value(value),
-		Json::Array(value) => builder.children(value.into_iter()
-		                                            .enumerate()
-		                                            .map(|(n, value)| (n.to_string(), vec![generate_extension(value, n.to_string())]))
-		                                            .collect::<BTreeMap<_, _>>()),
-		Json::Object(value) => builder.children(value.into_iter()
-		                                             .map(|(key, value)| (key.clone(), vec![generate_extension(value, key)]))
-		                                             .collect::<BTreeMap<_, _>>()),
-	}.build()
-}
-
-fn generate_rss(feed: Feed) -> impl Reply {
-	let body = ChannelBuilder::default()
-		.title("Rust Notifier")
-		.items(feed.status
-		           .iter()
-		           .chain(feed.notifications.iter())
-		           .take(50)
-		           .map(|entry|
-			           ItemBuilder::default()
-				                   .title(entry.title.clone())
-				                   .guid(GuidBuilder::default()
-					                                 .value(entry.guid.clone())
-					                                 .build())
-				                   .categories([CategoryBuilder::default()
-				                                                .name(entry.feed_name.as_ref().unwrap().clone())
-				                                                .build()])
-				                   .link(entry.link.clone())
-				                   .description(entry.description.clone())
-				                   .pub_date(entry.timestamp.map(|ts| ts.to_rfc2822()))
-				                   .extensions(map("x-notifier", map("x-notifier",
-					                   vec![
-						                   entry.color.clone().map(|color|
-							                   ExtensionBuilder::default()
-							                                    .name("x-notifier-color")
-							                                    .value(color)
-							                                    .build()
-						                   ),
-						                   entry.extra.clone().map(|extra| generate_extension(extra, "x-notifier-extra".to_string())),
-					                   ]
-					                   .into_iter()
-					                   .flatten()
-					                   .collect()
-				                   )))
-				                   .build())
-		           .collect::<Vec<_>>())
-		.build()
-		.to_string();
-	
-	reply::with_header(body, "Content-Type", "application/xml; charset=UTF-8")
+}
+
+// Path segments are not decoded by warp
+fn decode(segment: &str) -> String {
+	percent_decode_str(segment).decode_utf8_lossy().to_string()
+}
+
+// Feeds outside of token's scopes are not found either
+fn feed_not_found(name: &str) -> Rejection {
+	reject::custom(NotFoundReject(format!("Feed {} not found", name)))
+}
+
+// Feed formats have a single channel, named after the feed if there is only one
+#[derive(Serialize)]
+struct FlatPage {
+	#[serde(flatten)]
+	feed: Feed,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	next: Option<String>,
+}
+
+fn feed_title(mut names: impl Iterator<Item = String>, flat: bool) -> String {
+	match (names.next(), names.next()) {
+		(Some(name), None) if !flat => name,
+		_ => "Rust Notifier".to_string(),
+	}
 }
 
 #[derive(Debug)]
 struct RegexpReject(regex::Error);
 impl Reject for RegexpReject {}
 
-#[derive(Serialize)]
+#[derive(Debug)]
+struct BadRequestReject(String);
+impl Reject for BadRequestReject {}
+
+// Unlike reject::not_found, it isn't replaced by rejections of other routes with the same path
+#[derive(Debug)]
+struct NotFoundReject(String);
+impl Reject for NotFoundReject {}
+
+#[derive(Debug)]
+struct ConflictReject(String);
+impl Reject for ConflictReject {}
+
+#[derive(Debug)]
+struct InternalReject(String);
+impl Reject for InternalReject {}
+
+#[derive(Serialize, ToSchema)]
 struct ErrorMessage {
 	code: u16,
 	message: String,
 }
 
-async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
+// Access log under "access" target, so it can be filtered separately
+fn log_request(info: warp::log::Info) {
+	let route = route_name(info.path());
+	let status = info.status().as_u16();
+	
+	metrics::record_http_request(route, info.method().as_str(), status, info.elapsed());
+	
+	info!(target: "access",
+	      remote = info.remote_addr().map(tracing::field::display),
+	      method = %info.method(),
+	      path = info.path(),
+	      route,
+	      status,
+	      elapsed_ms = info.elapsed().as_millis() as u64,
+	      user_agent = info.user_agent(),
+	      referer = info.referer(),
+	      "{} {} {}", info.method(), info.path(), status);
+}
+
+// Route of the request for metrics, without feed names and other parameters
+fn route_name(path: &str) -> &'static str {
+	let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+	
+	match segments.as_slice() {
+		[""] => "/",
+		["feeds"] => "/feeds",
+		["feeds", _] => "/feeds/{file}",
+		["ws"] => "/ws",
+		["healthz"] => "/healthz",
+		["readyz"] => "/readyz",
+		["status"] => "/status",
+		["metrics"] => "/metrics",
+		["api", "v1", "feeds"] => "/api/v1/feeds",
+		["api", "v1", "feeds", _] => "/api/v1/feeds/{name}",
+		["api", "v1", "feeds", _, "entries", _] => "/api/v1/feeds/{name}/entries/{guid}",
+		["api", "v1", "providers"] => "/api/v1/providers",
+		["api", "v1", "errors"] => "/api/v1/errors",
+		["api", "v1", "openapi.json"] => "/api/v1/openapi.json",
+		_ => "other",
+	}
+}
+
+// Passes if the encoding is the most preferred one, gzip wins ties as it's much faster than brotli.
+// Upgraded connections, eg. websockets, aren't compressed
+fn encoding(name: &'static str) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+	warp::header::optional::<String>("accept-encoding")
+	     .and(warp::header::optional::<String>("upgrade"))
+	     .and_then(move |header: Option<String>, upgrade: Option<String>| future::ready(match header.as_deref().and_then(preferred_encoding) {
+		     Some(preferred) if preferred == name && upgrade.is_none() => Ok(()),
+		     _ => Err(reject::not_found()),
+	     }))
+	     .untuple_one()
+}
+
+fn preferred_encoding(header: &str) -> Option<&'static str> {
+	let quality = |name: &str| header.split(',')
+	                                 .filter_map(|encoding| {
+		                                 let mut parts = encoding.split(';');
+		                                 let encoding = parts.next()?.trim();
+		                                 let quality = parts.find_map(|param| param.trim().strip_prefix("q="))
+		                                                    .map_or(Some(1.0), |quality| quality.trim().parse::<f32>().ok())?;
+		                                 (encoding == name || encoding == "*").then_some(quality)
+	                                 })
+	                                 .fold(0.0, f32::max);
+	
+	let (gzip, br) = (quality("gzip"), quality("br"));
+	
+	if gzip > 0.0 && gzip >= br {
+		Some("gzip")
+	} else if br > 0.0 {
+		Some("br")
+	} else {
+		None
+	}
+}
+
+async fn handle_rejection(err: Rejection) -> Result<Box<dyn Reply>, Infallible> {
+	if let Some(NotModifiedReject(cache)) = err.find() {
+		return Ok(Box::new(cache.apply(reply::with_status(reply(), StatusCode::NOT_MODIFIED))));
+	}
+	
 	let code;
 	let mut message = None;
 	
@@ -172,10 +478,36 @@ async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
 	} else if let Some(e) = err.find::<RegexpReject>() {
 		code = StatusCode::BAD_REQUEST;
 		message = Some(e.0.to_string());
+	} else if let Some(e) = err.find::<BadRequestReject>() {
+		code = StatusCode::BAD_REQUEST;
+		message = Some(e.0.clone());
+	} else if let Some(e) = err.find::<BodyDeserializeError>() {
+		code = StatusCode::BAD_REQUEST;
+		message = Some(e.to_string());
+	} else if err.find::<UnauthorizedReject>().is_some() {
+		code = StatusCode::UNAUTHORIZED;
+	} else if err.find::<ForbiddenReject>().is_some() {
+		code = StatusCode::FORBIDDEN;
+	} else if let Some(e) = err.find::<NotFoundReject>() {
+		code = StatusCode::NOT_FOUND;
+		message = Some(e.0.clone());
+	} else if let Some(e) = err.find::<ConflictReject>() {
+		code = StatusCode::CONFLICT;
+		message = Some(e.0.clone());
+	} else if let Some(e) = err.find::<InternalReject>() {
+		error!(error = %e.0, "Internal error");
+		code = StatusCode::INTERNAL_SERVER_ERROR;
+		message = Some(e.0.clone());
+	} else if err.find::<reject::PayloadTooLarge>().is_some() {
+		code = StatusCode::PAYLOAD_TOO_LARGE;
+	} else if err.find::<reject::LengthRequired>().is_some() {
+		code = StatusCode::LENGTH_REQUIRED;
+	} else if err.find::<reject::UnsupportedMediaType>().is_some() {
+		code = StatusCode::UNSUPPORTED_MEDIA_TYPE;
 	} else if let Some(_) = err.find::<reject::MethodNotAllowed>() {
 		code = StatusCode::METHOD_NOT_ALLOWED;
 	} else {
-		eprintln!("unhandled rejection: {:?}", err);
+		error!(rejection = ?err, "Unhandled rejection");
 		code = StatusCode::INTERNAL_SERVER_ERROR;
 	}
 	
@@ -184,5 +516,76 @@ async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
 		message: message.unwrap_or(code.to_string()),
 	});
 	
-	Ok(reply::with_status(json, code))
+	let reply = reply::with_status(json, code);
+	
+	if code == StatusCode::UNAUTHORIZED {
+		Ok(Box::new(reply::with_header(reply, "WWW-Authenticate", "Basic realm=\"rust-notifier\", Bearer")) as Box<dyn Reply>)
+	} else {
+		Ok(Box::new(reply) as Box<dyn Reply>)
+	}
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("Invalid bind address {0}")]
+pub struct InvalidBindError(String);
+
+#[derive(Debug, Copy, Clone, Error)]
+#[error("Web interface has no addresses to bind")]
+pub struct NoAddressError;
+
+#[derive(Debug, Copy, Clone, Error)]
+#[error("Both tlsCert and tlsKey are required for TLS")]
+pub struct IncompleteTlsError;
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+	use serde_json::json;
+	use super::*;
+	use crate::feeds::Feeds;
+	use crate::utils::Map;
+	
+	fn routes() -> impl Filter<Extract = (impl Reply,), Error = Infallible> + Clone {
+		let state = State::new(Map::new(), PathBuf::new(), Duration::from_secs(600));
+		let mut feeds = Feeds::new();
+		feeds.insert("news".to_string(), Feed::new());
+		state.feeds.store(Arc::new(feeds));
+		
+		let tokens: Vec<TokenConfig> = serde_json::from_value(json!([
+			{ "token": "writer", "scopes": ["write"] },
+			{ "token": "other", "scopes": ["read:other"] },
+			{ "token": "reader", "scopes": ["read"] },
+		])).unwrap();
+		let auth = authenticate(Arc::new(tokens));
+		
+		let options = FeedOptions { rss: true, limit: 10, public_url: None, https: false };
+		
+		feeds_get(state.clone(), auth.clone(), options.clone())
+			.or(feed_file_get(state, auth, options))
+			.recover(handle_rejection)
+	}
+	
+	async fn status(path: &str, token: &str) -> StatusCode {
+		warp::test::request().path(path)
+		                     .header("authorization", format!("Bearer {}", token))
+		                     .header("if-none-match", "*")
+		                     .reply(&routes())
+		                     .await
+		                     .status()
+	}
+	
+	#[tokio::test]
+	async fn checks_access_before_not_modified() {
+		assert_eq!(status("/feeds", "writer").await, StatusCode::FORBIDDEN);
+		assert_eq!(status("/feeds", "unknown").await, StatusCode::UNAUTHORIZED);
+		assert_eq!(status("/feeds?filter=(", "reader").await, StatusCode::BAD_REQUEST);
+		assert_eq!(status("/feeds/news.rss", "other").await, StatusCode::NOT_FOUND);
+		assert_eq!(status("/feeds/missing.rss", "reader").await, StatusCode::NOT_FOUND);
+	}
+	
+	#[tokio::test]
+	async fn answers_not_modified_after_checks() {
+		assert_eq!(status("/feeds", "reader").await, StatusCode::NOT_MODIFIED);
+		assert_eq!(status("/feeds/news.rss", "reader").await, StatusCode::NOT_MODIFIED);
+	}
 }
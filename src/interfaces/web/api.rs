@@ -0,0 +1,336 @@
+use std::path::Path;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use chrono::serde::ts_milliseconds_option;
+use serde::Serialize;
+use warp::{Filter, Rejection, Reply, reply, reject};
+use warp::http::StatusCode;
+use tokio::sync::Mutex;
+use utoipa::{Modify, OpenApi, ToSchema};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, Http, HttpAuthScheme, SecurityScheme};
+
+use crate::utils::Map;
+use crate::state::State;
+use crate::config::{Config, ConfigFeedEntry};
+use crate::feeds::{Entry, Feed};
+use crate::providers::validate_provider_data;
+use super::{ErrorMessage, decode, feed_not_found, BadRequestReject, NotFoundReject, ConflictReject, InternalReject};
+use super::auth::Access;
+
+#[derive(OpenApi)]
+#[openapi(
+	info(title = "Rust Notifier", description = "Versioned REST API of the web interface"),
+	paths(list_feeds, get_feed, get_entry, create_feed, update_feed, delete_feed, list_providers, list_errors),
+	components(schemas(ErrorMessage)),
+	modifiers(&TokenAuth),
+)]
+struct ApiDoc;
+
+struct TokenAuth;
+
+impl Modify for TokenAuth {
+	fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+		let components = openapi.components.get_or_insert_with(Default::default);
+		components.add_security_scheme("token", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+		components.add_security_scheme("basic", SecurityScheme::Http(Http::new(HttpAuthScheme::Basic)));
+		components.add_security_scheme("queryToken", SecurityScheme::ApiKey(ApiKey::Query(ApiKeyValue::new("token"))));
+	}
+}
+
+#[derive(Serialize, ToSchema)]
+struct FeedInfo {
+	name: String,
+	provider: String,
+	color: Option<String>,
+	status: usize,
+	notifications: usize,
+	errors: usize,
+	// Timestamp of the newest entry, milliseconds since epoch
+	#[serde(rename="latestTimestamp", with = "ts_milliseconds_option")]
+	#[schema(value_type = Option<i64>)]
+	latest_timestamp: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct FeedDetails {
+	info: FeedInfo,
+	feed: Feed,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ProviderInfo {
+	name: String,
+	enabled: bool,
+	// Enabled provider is healthy if it loaded and none of its feeds failed in the last fetch
+	healthy: bool,
+	#[serde(rename="loadError")]
+	load_error: Option<String>,
+	// Milliseconds since epoch
+	#[serde(rename="lastFetch", with = "ts_milliseconds_option")]
+	#[schema(value_type = Option<i64>)]
+	last_fetch: Option<DateTime<Utc>>,
+	#[serde(rename="fetchDurationMs")]
+	fetch_duration_ms: Option<u64>,
+	feeds: usize,
+	#[serde(rename="failingFeeds")]
+	failing_feeds: usize,
+}
+
+fn feed_info(state: &State, name: &str) -> Option<FeedInfo> {
+	let feed_entries = state.feed_entries.load();
+	let config = feed_entries.get(name)?;
+	let feeds = state.feeds.load();
+	let feed = feeds.get(name);
+	
+	Some(FeedInfo {
+		name: name.to_string(),
+		provider: config.provider.clone(),
+		color: config.color.clone(),
+		status: feed.map_or(0, |feed| feed.status.len()),
+		notifications: feed.map_or(0, |feed| feed.notifications.len()),
+		errors: feed.map_or(0, |feed| feed.errors.len()),
+		latest_timestamp: feed.and_then(|feed| feed.iter().filter_map(|entry| entry.timestamp).max()),
+	})
+}
+
+/// List configured feeds
+#[utoipa::path(get, path = "/api/v1/feeds", security((), ("token" = []), ("basic" = []), ("queryToken" = [])), responses((status = 200, body = [FeedInfo])))]
+async fn list_feeds(access: Access, state: State) -> Result<impl Reply, Rejection> {
+	access.require_read()?;
+	
+	let names = state.feed_entries
+	                 .load()
+	                 .keys()
+	                 .filter(|name| access.can_read(name))
+	                 .cloned()
+	                 .collect::<Vec<_>>();
+	let feeds = names.iter()
+	                 .filter_map(|name| feed_info(&state, name))
+	                 .collect::<Vec<_>>();
+	
+	Ok(reply::json(&feeds))
+}
+
+/// Get feed with its entries
+#[utoipa::path(get, path = "/api/v1/feeds/{name}",
+	params(("name" = String, Path, description = "Feed name")),
+	security((), ("token" = []), ("basic" = []), ("queryToken" = [])),
+	responses((status = 200, body = FeedDetails), (status = 404, body = ErrorMessage)))]
+async fn get_feed(name: String, access: Access, state: State) -> Result<impl Reply, Rejection> {
+	let name = decode(&name);
+	if !access.can_read(&name) { return Err(feed_not_found(&name)) }
+	
+	let info = feed_info(&state, &name).ok_or_else(|| feed_not_found(&name))?;
+	let feed = state.feeds.load().get(&name).cloned().unwrap_or_else(Feed::new);
+	
+	Ok(reply::json(&FeedDetails { info, feed }))
+}
+
+/// Get entry of a feed by its guid
+#[utoipa::path(get, path = "/api/v1/feeds/{name}/entries/{guid}",
+	params(("name" = String, Path, description = "Feed name"), ("guid" = String, Path, description = "Entry guid")),
+	security((), ("token" = []), ("basic" = []), ("queryToken" = [])),
+	responses((status = 200, body = Entry), (status = 404, body = ErrorMessage)))]
+async fn get_entry(name: String, guid: String, access: Access, state: State) -> Result<impl Reply, Rejection> {
+	let (name, guid) = (decode(&name), decode(&guid));
+	if !access.can_read(&name) { return Err(feed_not_found(&name)) }
+	
+	let feeds = state.feeds.load();
+	let entry = feeds.get(&name)
+	                 .and_then(|feed| feed.iter().find(|entry| entry.guid == guid))
+	                 .ok_or_else(|| reject::custom(NotFoundReject(format!("Entry {} not found", guid))))?;
+	
+	Ok(reply::json(entry))
+}
+
+// Provider has to be enabled, otherwise the feed would never be fetched
+fn validate_entry(state: &State, entry: &ConfigFeedEntry) -> Result<(), Rejection> {
+	if !state.providers.load().get(&entry.provider).is_some_and(|status| status.enabled) {
+		return Err(reject::custom(BadRequestReject(format!("Provider {} is not enabled", entry.provider))))
+	}
+	
+	validate_provider_data(&entry.provider, &entry.provider_data)
+		.map_err(|err| reject::custom(BadRequestReject(format!("Invalid providerData: {}", err))))
+}
+
+async fn save_feeds(path: &Path, feeds: Map<ConfigFeedEntry>) -> anyhow::Result<()> {
+	// Other parts of the config are left as they are in the file, which has to exist
+	let mut config = Config::read(path).await?;
+	config.feeds = feeds;
+	config.save(path).await
+}
+
+// Saves changed feed entries to the config, then applies them and fetches feeds right away.
+// Providers fetch all their feeds together, so every feed is fetched, not just the changed one.
+async fn update_feeds(state: &State, lock: &Mutex<()>, update: impl FnOnce(&mut Map<ConfigFeedEntry>) -> Result<(), Rejection>) -> Result<(), Rejection> {
+	// Concurrent requests would overwrite each other's changes
+	let _guard = lock.lock().await;
+	let mut feeds = (**state.feed_entries.load()).clone();
+	update(&mut feeds)?;
+	
+	save_feeds(&state.config_path, feeds.clone()).await
+	                                             .map_err(|err| reject::custom(InternalReject(format!("Unable to save config: {}", err))))?;
+	
+	state.feed_entries.store(Arc::new(feeds));
+	state.refresh.notify_one();
+	Ok(())
+}
+
+/// Add feed
+#[utoipa::path(post, path = "/api/v1/feeds/{name}",
+	params(("name" = String, Path, description = "Feed name")),
+	request_body = ConfigFeedEntry,
+	security(("token" = []), ("basic" = []), ("queryToken" = [])),
+	responses((status = 201, body = ConfigFeedEntry), (status = 400, body = ErrorMessage), (status = 401, body = ErrorMessage), (status = 403, body = ErrorMessage), (status = 409, body = ErrorMessage)))]
+async fn create_feed(name: String, access: Access, entry: ConfigFeedEntry, state: State, lock: Arc<Mutex<()>>) -> Result<impl Reply, Rejection> {
+	access.require_write()?;
+	let name = decode(&name);
+	validate_entry(&state, &entry)?;
+	
+	update_feeds(&state, &lock, |feeds| {
+		if feeds.contains_key(&name) { return Err(reject::custom(ConflictReject(format!("Feed {} already exists", name)))) }
+		feeds.insert(name, entry.clone());
+		Ok(())
+	}).await?;
+	
+	Ok(reply::with_status(reply::json(&entry), StatusCode::CREATED))
+}
+
+/// Replace feed's config
+#[utoipa::path(put, path = "/api/v1/feeds/{name}",
+	params(("name" = String, Path, description = "Feed name")),
+	request_body = ConfigFeedEntry,
+	security(("token" = []), ("basic" = []), ("queryToken" = [])),
+	responses((status = 200, body = ConfigFeedEntry), (status = 400, body = ErrorMessage), (status = 401, body = ErrorMessage), (status = 403, body = ErrorMessage), (status = 404, body = ErrorMessage)))]
+async fn update_feed(name: String, access: Access, entry: ConfigFeedEntry, state: State, lock: Arc<Mutex<()>>) -> Result<impl Reply, Rejection> {
+	access.require_write()?;
+	let name = decode(&name);
+	validate_entry(&state, &entry)?;
+	
+	update_feeds(&state, &lock, |feeds| {
+		let existing = feeds.get_mut(&name).ok_or_else(|| feed_not_found(&name))?;
+		*existing = entry.clone();
+		Ok(())
+	}).await?;
+	
+	Ok(reply::json(&entry))
+}
+
+/// Remove feed
+#[utoipa::path(delete, path = "/api/v1/feeds/{name}",
+	params(("name" = String, Path, description = "Feed name")),
+	security(("token" = []), ("basic" = []), ("queryToken" = [])),
+	responses((status = 204), (status = 401, body = ErrorMessage), (status = 403, body = ErrorMessage), (status = 404, body = ErrorMessage)))]
+async fn delete_feed(name: String, access: Access, state: State, lock: Arc<Mutex<()>>) -> Result<impl Reply, Rejection> {
+	access.require_write()?;
+	let name = decode(&name);
+	
+	update_feeds(&state, &lock, |feeds| {
+		feeds.remove(&name).ok_or_else(|| feed_not_found(&name))?;
+		Ok(())
+	}).await?;
+	
+	Ok(StatusCode::NO_CONTENT)
+}
+
+/// List providers and their health
+#[utoipa::path(get, path = "/api/v1/providers", security((), ("token" = []), ("basic" = []), ("queryToken" = [])), responses((status = 200, body = [ProviderInfo])))]
+async fn list_providers(access: Access, state: State) -> Result<impl Reply, Rejection> {
+	access.require_read()?;
+	
+	let providers = state.providers
+	                     .load()
+	                     .iter()
+	                     .map(|(name, status)| ProviderInfo {
+		                     name: name.clone(),
+		                     enabled: status.enabled,
+		                     healthy: status.is_healthy(),
+		                     load_error: status.load_error.clone(),
+		                     last_fetch: status.last_fetch,
+		                     fetch_duration_ms: status.fetch_duration.map(|duration| duration.as_millis() as u64),
+		                     feeds: status.feeds,
+		                     failing_feeds: status.failing_feeds,
+	                     })
+	                     .collect::<Vec<_>>();
+	
+	Ok(reply::json(&providers))
+}
+
+/// List errors of all feeds
+#[utoipa::path(get, path = "/api/v1/errors", security((), ("token" = []), ("basic" = []), ("queryToken" = [])), responses((status = 200, body = [Entry])))]
+async fn list_errors(access: Access, state: State) -> Result<impl Reply, Rejection> {
+	access.require_read()?;
+	
+	let feeds = state.feeds.load();
+	let errors = feeds.iter()
+	                  .filter(|(name, _)| access.can_read(name))
+	                  .flat_map(|(_, feed)| feed.errors.iter())
+	                  .collect::<Vec<_>>();
+	
+	Ok(reply::json(&errors))
+}
+
+// /api/v1/...
+pub fn routes(state: State, auth: impl Filter<Extract = (Access,), Error = Rejection> + Clone + Send) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	let state = warp::any().map(move || state.clone());
+	let lock = Arc::new(Mutex::new(()));
+	let lock = warp::any().map(move || lock.clone());
+	let body = warp::body::content_length_limit(1024 * 64).and(warp::body::json());
+	
+	let feeds = warp::path!("api" / "v1" / "feeds")
+	                 .and(auth.clone())
+	                 .and(state.clone())
+	                 .and_then(list_feeds);
+	
+	let feed = warp::path!("api" / "v1" / "feeds" / String)
+	                .and(auth.clone())
+	                .and(state.clone())
+	                .and_then(get_feed);
+	
+	let entry = warp::path!("api" / "v1" / "feeds" / String / "entries" / String)
+	                 .and(auth.clone())
+	                 .and(state.clone())
+	                 .and_then(get_entry);
+	
+	let providers = warp::path!("api" / "v1" / "providers")
+	                     .and(auth.clone())
+	                     .and(state.clone())
+	                     .and_then(list_providers);
+	
+	let errors = warp::path!("api" / "v1" / "errors")
+	                  .and(auth.clone())
+	                  .and(state.clone())
+	                  .and_then(list_errors);
+	
+	let openapi = warp::path!("api" / "v1" / "openapi.json")
+	                   .map(|| reply::json(&ApiDoc::openapi()));
+	
+	let create = warp::post()
+	                  .and(warp::path!("api" / "v1" / "feeds" / String))
+	                  .and(auth.clone())
+	                  .and(body)
+	                  .and(state.clone())
+	                  .and(lock.clone())
+	                  .and_then(create_feed);
+	
+	let update = warp::put()
+	                  .and(warp::path!("api" / "v1" / "feeds" / String))
+	                  .and(auth.clone())
+	                  .and(body)
+	                  .and(state.clone())
+	                  .and(lock.clone())
+	                  .and_then(update_feed);
+	
+	let delete = warp::delete()
+	                  .and(warp::path!("api" / "v1" / "feeds" / String))
+	                  .and(auth)
+	                  .and(state)
+	                  .and(lock)
+	                  .and_then(delete_feed);
+	
+	warp::get()
+	     .and(feeds.or(feed).or(entry).or(providers).or(errors).or(openapi))
+	     .or(create)
+	     .or(update)
+	     .or(delete)
+}
@@ -0,0 +1,209 @@
+use std::convert::TryFrom;
+use std::sync::Arc;
+use serde::Deserialize;
+use regex::Regex;
+use warp::{Filter, Rejection, reject};
+use warp::reject::Reject;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use subtle::ConstantTimeEq;
+use futures::future;
+use thiserror::Error;
+
+#[derive(Deserialize)]
+pub struct TokenConfig {
+	// Accepted in "Authorization: Bearer <token>" header or in ?token=<token> query parameter, for RSS readers
+	token: Option<String>,
+	// Accepted in "Authorization: Basic" header
+	username: Option<String>,
+	password: Option<String>,
+	scopes: Vec<Scope>,
+}
+
+impl TokenConfig {
+	// Bearer token which can read feeds, used by CLI commands talking to the running instance
+	pub fn read_token(&self) -> Option<&str> {
+		let can_read = self.scopes.iter().any(|scope| !matches!(scope, Scope::Write));
+		self.token.as_deref().filter(|_| can_read)
+	}
+	
+	fn matches(&self, credentials: &Credentials) -> bool {
+		match (credentials, &self.token, &self.username, &self.password) {
+			(Credentials::Token(token), Some(expected), _, _) => secure_eq(token, expected),
+			(Credentials::Basic(username, password), _, Some(expected_username), Some(expected_password)) => {
+				// Both are compared, so time doesn't tell if the username was right
+				secure_eq(username, expected_username) & secure_eq(password, expected_password)
+			},
+			_ => false,
+		}
+	}
+}
+
+// "read", "read:<feed name regex>" or "write"
+#[derive(Deserialize, Clone)]
+#[serde(try_from = "String")]
+enum Scope {
+	// All feeds
+	Read,
+	// Feeds with names matching the regex
+	ReadFeeds(Regex),
+	// Adding, changing and removing feeds
+	Write,
+}
+
+impl TryFrom<String> for Scope {
+	type Error = String;
+	
+	fn try_from(scope: String) -> Result<Self, Self::Error> {
+		match scope.as_str() {
+			"read" => Ok(Scope::Read),
+			"write" => Ok(Scope::Write),
+			_ => match scope.strip_prefix("read:") {
+				// Anchored and case sensitive like feed names, so "read:news" doesn't grant "hackernews" or "NEWS"
+				Some(filter) => Regex::new(&format!("^(?:{})$", filter)).map(Scope::ReadFeeds).map_err(|err| err.to_string()),
+				None => Err(format!("Unknown scope {}, expected read, read:<regex> or write", scope)),
+			},
+		}
+	}
+}
+
+enum Credentials {
+	Token(String),
+	Basic(String, String),
+}
+
+// What the request is allowed to do
+#[derive(Clone)]
+pub struct Access {
+	scopes: Vec<Scope>,
+}
+
+impl Access {
+	pub fn can_read(&self, feed: &str) -> bool {
+		self.scopes.iter().any(|scope| match scope {
+			Scope::Read => true,
+			Scope::ReadFeeds(filter) => filter.is_match(feed),
+			Scope::Write => false,
+		})
+	}
+	
+	// Reading at least some feeds
+	pub fn require_read(&self) -> Result<(), Rejection> {
+		if self.scopes.iter().any(|scope| !matches!(scope, Scope::Write)) { Ok(()) } else { Err(reject::custom(ForbiddenReject)) }
+	}
+	
+	pub fn require_write(&self) -> Result<(), Rejection> {
+		if self.scopes.iter().any(|scope| matches!(scope, Scope::Write)) { Ok(()) } else { Err(reject::custom(ForbiddenReject)) }
+	}
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+	token: Option<String>,
+}
+
+pub fn check_tokens(tokens: &[TokenConfig]) -> Result<(), InvalidTokenConfigError> {
+	let valid = tokens.iter().all(|config| config.token.is_some() || (config.username.is_some() && config.password.is_some()));
+	if valid { Ok(()) } else { Err(InvalidTokenConfigError) }
+}
+
+// Extracts access of the request, rejects requests without valid credentials if any tokens are configured
+pub fn authenticate(tokens: Arc<Vec<TokenConfig>>) -> impl Filter<Extract = (Access,), Error = Rejection> + Clone {
+	warp::header::optional::<String>("authorization")
+	     .and(warp::query::<TokenQuery>())
+	     .and_then(move |header: Option<String>, query: TokenQuery| future::ready(authorize(&tokens, header.as_deref(), query.token)))
+}
+
+fn authorize(tokens: &[TokenConfig], header: Option<&str>, query_token: Option<String>) -> Result<Access, Rejection> {
+	// Without tokens everything can be read, but nothing changed
+	if tokens.is_empty() { return Ok(Access { scopes: vec![Scope::Read] }) }
+	
+	let credentials = match header {
+		Some(header) => parse_header(header),
+		None => query_token.map(Credentials::Token),
+	}.ok_or_else(|| reject::custom(UnauthorizedReject))?;
+	
+	tokens.iter()
+	      .find(|config| config.matches(&credentials))
+	      .map(|config| Access { scopes: config.scopes.clone() })
+	      .ok_or_else(|| reject::custom(UnauthorizedReject))
+}
+
+fn parse_header(header: &str) -> Option<Credentials> {
+	if let Some(token) = header.strip_prefix("Bearer ") {
+		Some(Credentials::Token(token.trim().to_string()))
+	} else if let Some(encoded) = header.strip_prefix("Basic ") {
+		let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+		let (username, password) = decoded.split_once(':')?;
+		Some(Credentials::Basic(username.to_string(), password.to_string()))
+	} else {
+		None
+	}
+}
+
+fn secure_eq(a: &str, b: &str) -> bool {
+	a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+#[derive(Debug)]
+pub struct UnauthorizedReject;
+impl Reject for UnauthorizedReject {}
+
+#[derive(Debug)]
+pub struct ForbiddenReject;
+impl Reject for ForbiddenReject {}
+
+#[derive(Debug, Copy, Clone, Error)]
+#[error("Every web token needs either token or username and password")]
+pub struct InvalidTokenConfigError;
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn access(scopes: &[&str]) -> Access {
+		Access { scopes: scopes.iter().map(|scope| Scope::try_from(scope.to_string()).unwrap()).collect() }
+	}
+	
+	#[test]
+	fn feed_scope_is_anchored() {
+		let access = access(&["read:news"]);
+		
+		assert!(access.can_read("news"));
+		assert!(!access.can_read("NEWS"));
+		assert!(!access.can_read("hackernews"));
+		assert!(!access.can_read("news-private"));
+	}
+	
+	#[test]
+	fn feed_scope_alternatives() {
+		let access = access(&["read:news|yt-.*"]);
+		
+		assert!(access.can_read("news"));
+		assert!(access.can_read("yt-music"));
+		assert!(!access.can_read("hackernews"));
+		assert!(!access.can_read("my-yt-music"));
+	}
+	
+	#[test]
+	fn read_and_write_scopes() {
+		assert!(access(&["read"]).can_read("anything"));
+		assert!(access(&["read"]).require_write().is_err());
+		assert!(access(&["write"]).require_read().is_err());
+		assert!(!access(&["write"]).can_read("anything"));
+		assert!(access(&["read:a", "write"]).require_write().is_ok());
+	}
+	
+	#[test]
+	fn invalid_scopes() {
+		assert!(Scope::try_from("admin".to_string()).is_err());
+		assert!(Scope::try_from("read:(".to_string()).is_err());
+	}
+	
+	#[test]
+	fn parses_credentials() {
+		assert!(matches!(parse_header("Bearer abc "), Some(Credentials::Token(token)) if token == "abc"));
+		assert!(matches!(parse_header("Basic dXNlcjpwYXNz"), Some(Credentials::Basic(user, pass)) if user == "user" && pass == "pass"));
+		assert!(parse_header("Digest abc").is_none());
+	}
+}
@@ -0,0 +1,145 @@
+use chrono::{DateTime, Utc};
+use warp::{Filter, Rejection, Reply, reply, reject};
+use warp::reject::Reject;
+use warp::path::FullPath;
+
+use crate::state::State;
+use crate::utils::hash;
+
+// Responses only change when feeds are fetched again, so they are validated by the snapshot
+#[derive(Debug, Clone)]
+pub struct CacheHeaders {
+	etag: String,
+	last_modified: DateTime<Utc>,
+	// Seconds until the next scheduled fetch
+	max_age: i64,
+	// Client already has the current response
+	fresh: bool,
+}
+
+impl CacheHeaders {
+	fn new(state: &State, path: &FullPath, query: &str, authorization: Option<&str>, if_none_match: Option<&str>, if_modified_since: Option<&str>) -> Self {
+		let created = state.feeds.load().created;
+		let next_fetch = created + chrono::Duration::from_std(state.fetch_interval).unwrap_or_default();
+		
+		let mut headers = CacheHeaders {
+			// Different queries and tokens get different responses from the same snapshot
+			etag: format!("\"{}\"", hash(&(created, path.as_str(), query, authorization))),
+			last_modified: created,
+			max_age: (next_fetch - Utc::now()).num_seconds().max(0),
+			fresh: false,
+		};
+		
+		headers.fresh = headers.matches(if_none_match, if_modified_since);
+		headers
+	}
+	
+	// If-Modified-Since is ignored when If-None-Match is present, as in RFC 9110
+	// ETags are compared weakly, the tag is the same for every content coding of the response
+	fn matches(&self, if_none_match: Option<&str>, if_modified_since: Option<&str>) -> bool {
+		match (if_none_match, if_modified_since) {
+			(Some(tags), _) => tags.split(',')
+			                       .map(|tag| tag.trim())
+			                       .any(|tag| tag == "*" || tag.trim_start_matches("W/") == self.etag),
+			(None, Some(since)) => DateTime::parse_from_rfc2822(since).is_ok_and(|since| self.last_modified.timestamp() <= since.timestamp()),
+			(None, None) => false,
+		}
+	}
+	
+	// Rejects with NotModifiedReject when the client already has the current response, before it's generated.
+	// Called after access and the request are checked, so errors and hidden feeds aren't answered with 304.
+	pub fn check(&self) -> Result<(), Rejection> {
+		if self.fresh { Err(reject::custom(NotModifiedReject(self.clone()))) } else { Ok(()) }
+	}
+	
+	pub fn apply(&self, reply: impl Reply) -> impl Reply {
+		let reply = reply::with_header(reply, "ETag", format!("W/{}", self.etag));
+		let reply = reply::with_header(reply, "Last-Modified", self.last_modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+		reply::with_header(reply, "Cache-Control", format!("private, max-age={}", self.max_age))
+	}
+}
+
+// Cache headers of the response, CacheHeaders::check tells if the client already has it
+pub fn conditional(state: State) -> impl Filter<Extract = (CacheHeaders,), Error = Rejection> + Clone {
+	warp::path::full()
+	     .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+	     .and(warp::header::optional::<String>("authorization"))
+	     .and(warp::header::optional::<String>("if-none-match"))
+	     .and(warp::header::optional::<String>("if-modified-since"))
+	     .map(move |path: FullPath, query: String, authorization: Option<String>, if_none_match: Option<String>, if_modified_since: Option<String>| {
+		     CacheHeaders::new(&state, &path, &query, authorization.as_deref(), if_none_match.as_deref(), if_modified_since.as_deref())
+	     })
+}
+
+#[derive(Debug)]
+pub struct NotModifiedReject(pub CacheHeaders);
+impl Reject for NotModifiedReject {}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+	use std::time::Duration;
+	use super::*;
+	use crate::utils::Map;
+	
+	fn state() -> State {
+		State::new(Map::new(), PathBuf::new(), Duration::from_secs(600))
+	}
+	
+	fn headers(etag: &str) -> CacheHeaders {
+		CacheHeaders {
+			etag: etag.to_string(),
+			last_modified: DateTime::parse_from_rfc2822("Mon, 01 Jan 2024 10:00:00 GMT").unwrap().with_timezone(&Utc),
+			max_age: 60,
+			fresh: false,
+		}
+	}
+	
+	#[test]
+	fn matches_etags() {
+		let headers = headers("\"ABC\"");
+		
+		assert!(headers.matches(Some("\"ABC\""), None));
+		assert!(headers.matches(Some("W/\"ABC\""), None));
+		assert!(headers.matches(Some("\"X\", W/\"ABC\""), None));
+		assert!(headers.matches(Some("*"), None));
+		assert!(!headers.matches(Some("\"X\""), None));
+		// If-None-Match takes precedence
+		assert!(!headers.matches(Some("\"X\""), Some("Mon, 01 Jan 2024 11:00:00 GMT")));
+	}
+	
+	#[test]
+	fn matches_modified_since() {
+		let headers = headers("\"ABC\"");
+		
+		assert!(headers.matches(None, Some("Mon, 01 Jan 2024 10:00:00 GMT")));
+		assert!(headers.matches(None, Some("Mon, 01 Jan 2024 11:00:00 GMT")));
+		assert!(!headers.matches(None, Some("Mon, 01 Jan 2024 09:59:59 GMT")));
+		assert!(!headers.matches(None, Some("yesterday")));
+		assert!(!headers.matches(None, None));
+	}
+	
+	#[tokio::test]
+	async fn emits_weak_etag() {
+		let filter = conditional(state()).map(|headers: CacheHeaders| headers.apply(""));
+		let response = warp::test::request().path("/feeds").filter(&filter).await.unwrap().into_response();
+		let etag = response.headers()["etag"].to_str().unwrap();
+		
+		assert!(etag.starts_with("W/\""));
+		assert!(response.headers()["cache-control"].to_str().unwrap().starts_with("private, max-age="));
+	}
+	
+	#[tokio::test]
+	async fn rejects_current_etag() {
+		let filter = conditional(state());
+		let headers = warp::test::request().path("/feeds?a=1").filter(&filter).await.unwrap();
+		let etag = format!("W/{}", headers.etag);
+		
+		let headers = warp::test::request().path("/feeds?a=1").header("if-none-match", &etag).filter(&filter).await.unwrap();
+		assert!(headers.check().unwrap_err().find::<NotModifiedReject>().is_some());
+		
+		// Other queries and tokens have other tags
+		assert!(warp::test::request().path("/feeds?a=2").header("if-none-match", &etag).filter(&filter).await.unwrap().check().is_ok());
+		assert!(warp::test::request().path("/feeds?a=1").header("if-none-match", &etag).header("authorization", "Bearer x").filter(&filter).await.unwrap().check().is_ok());
+	}
+}
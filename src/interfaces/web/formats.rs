@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use warp::{Reply, reply};
+use rss::{ChannelBuilder, ItemBuilder, CategoryBuilder, GuidBuilder};
+use rss::extension::{Extension, ExtensionBuilder, ExtensionMap};
+use atom_syndication as atom;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+use crate::utils::Json;
+use crate::feeds::{Entry, Feed};
+
+const MEDIA_NAMESPACE: &str = "http://search.yahoo.com/mrss/";
+
+// Metadata of the generated feed
+pub struct Channel {
+	pub title: String,
+	// URL of the generated feed itself
+	pub link: Option<String>,
+	// When feeds were fetched
+	pub updated: DateTime<Utc>,
+	// Minutes until the next fetch
+	pub ttl: u64,
+	// Maximum number of items
+	pub limit: usize,
+}
+
+fn map<T>(key: &str, value: T) -> BTreeMap<String, T> {
+	let mut map = BTreeMap::new();
+	map.insert(key.to_string(), value);
+	map
+}
+
+fn items(feed: &Feed, limit: usize) -> impl Iterator<Item = &Entry> {
+	feed.status
+	    .iter()
+	    .chain(feed.notifications.iter())
+	    .take(limit)
+}
+
+fn generate_extension(value: Json, name: String) -> Extension {
+	let mut builder = ExtensionBuilder::default();
+	builder.name(name.to_string());
+	
+	match value {
+		Json::Null => &mut builder,
+		Json::Bool(value) => builder.value(value.to_string()),
+		Json::Number(value) => builder.value(value.to_string()),
+		Json::String(value) => builder.value(value),
+		Json::Array(value) => builder.children(value.into_iter()
+		                                            .enumerate()
+		                                            .map(|(n, value)| (n.to_string(), vec![generate_extension(value, n.to_string())]))
+		                                            .collect::<BTreeMap<_, _>>()),
+		Json::Object(value) => builder.children(value.into_iter()
+		                                             .map(|(key, value)| (key.clone(), vec![generate_extension(value, key)]))
+		                                             .collect::<BTreeMap<_, _>>()),
+	}.build()
+}
+
+// Color, extra and thumbnail of the entry, same in RSS and Atom
+fn generate_extensions(entry: &Entry) -> ExtensionMap {
+	let notifier = vec![
+		entry.color.clone().map(|color|
+			ExtensionBuilder::default()
+			                 .name("x-notifier-color")
+			                 .value(color)
+			                 .build()
+		),
+		entry.extra.clone().map(|extra| generate_extension(extra, "x-notifier-extra".to_string())),
+	];
+	
+	let mut extensions = map("x-notifier", map("x-notifier", notifier.into_iter().flatten().collect()));
+	
+	if let Some(url) = entry.image_url.clone() {
+		let thumbnail = ExtensionBuilder::default()
+		                                 .name("media:thumbnail")
+		                                 .attrs(map("url", url))
+		                                 .build();
+		
+		extensions.insert("media".to_string(), map("thumbnail", vec![thumbnail]));
+	}
+	
+	extensions
+}
+
+fn atom_extension(extension: Extension) -> atom::extension::Extension {
+	atom::extension::Extension {
+		name: extension.name,
+		value: extension.value,
+		attrs: extension.attrs,
+		children: extension.children
+		                   .into_iter()
+		                   .map(|(name, children)| (name, children.into_iter().map(atom_extension).collect()))
+		                   .collect(),
+	}
+}
+
+fn atom_extensions(extensions: ExtensionMap) -> atom::extension::ExtensionMap {
+	extensions.into_iter()
+	          .map(|(prefix, extensions)| (prefix, extensions.into_iter()
+	                                                         .map(|(name, extensions)| (name, extensions.into_iter().map(atom_extension).collect()))
+	                                                         .collect()))
+	          .collect()
+}
+
+pub fn generate_rss(channel: &Channel, feed: Feed) -> impl Reply {
+	let body = ChannelBuilder::default()
+		.title(channel.title.clone())
+		.link(channel.link.clone().unwrap_or_default())
+		.last_build_date(channel.updated.to_rfc2822())
+		.ttl(channel.ttl.to_string())
+		.namespaces(map("media", MEDIA_NAMESPACE.to_string()))
+		.items(items(&feed, channel.limit)
+		           .map(|entry|
+			           ItemBuilder::default()
+				                   .title(entry.title.clone())
+				                   .guid(GuidBuilder::default()
+					                                 .value(entry.guid.clone())
+					                                 .build())
+				                   .categories(entry.feed_name
+				                                    .iter()
+				                                    .map(|name| CategoryBuilder::default().name(name.clone()).build())
+				                                    .collect::<Vec<_>>())
+				                   .link(entry.link.clone())
+				                   .description(entry.description.clone())
+				                   .pub_date(entry.timestamp.map(|ts| ts.to_rfc2822()))
+				                   .extensions(generate_extensions(entry))
+				                   .build())
+		           .collect::<Vec<_>>())
+		.build()
+		.to_string();
+	
+	reply::with_header(body, "Content-Type", "application/xml; charset=UTF-8")
+}
+
+pub fn generate_atom(channel: &Channel, feed: Feed) -> impl Reply {
+	let id = channel.link
+	                .clone()
+	                .unwrap_or_else(|| format!("urn:rust-notifier:{}", utf8_percent_encode(&channel.title, NON_ALPHANUMERIC)));
+	
+	let body = atom::FeedBuilder::default()
+		.title(channel.title.clone())
+		.id(id)
+		.updated(channel.updated)
+		.links(channel.link.iter().map(|link| atom::LinkBuilder::default().href(link.clone()).rel("self").build()).collect::<Vec<_>>())
+		.namespaces(map("media", MEDIA_NAMESPACE.to_string()))
+		.entries(items(&feed, channel.limit)
+		             .map(|entry|
+			             atom::EntryBuilder::default()
+				                         .title(entry.title.clone())
+				                         .id(entry.guid.clone())
+				                         // Required by Atom, entries without timestamp are as old as the feed
+				                         .updated(entry.timestamp.unwrap_or(channel.updated))
+				                         .published(entry.timestamp.map(Into::into))
+				                         .links(entry.link.iter().map(|link| atom::LinkBuilder::default().href(link.clone()).build()).collect::<Vec<_>>())
+				                         .summary(entry.description.clone().map(atom::Text::plain))
+				                         .categories(entry.feed_name.iter().map(|name| atom::CategoryBuilder::default().term(name.clone()).build()).collect::<Vec<_>>())
+				                         .extensions(atom_extensions(generate_extensions(entry)))
+				                         .build())
+		             .collect::<Vec<_>>())
+		.build()
+		.to_string();
+	
+	reply::with_header(body, "Content-Type", "application/atom+xml; charset=UTF-8")
+}
+
+// https://www.jsonfeed.org/version/1.1/
+#[derive(Serialize)]
+struct JsonFeed<'a> {
+	version: &'static str,
+	title: &'a str,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	feed_url: Option<&'a str>,
+	items: Vec<JsonFeedItem<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonFeedItem<'a> {
+	id: &'a str,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	url: Option<&'a str>,
+	title: &'a str,
+	// Either content_text or content_html is required
+	content_text: &'a str,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	image: Option<&'a str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	date_published: Option<String>,
+	tags: Vec<&'a str>,
+	// Custom extension
+	#[serde(rename="_notifier")]
+	notifier: JsonFeedExtension<'a>,
+}
+
+#[derive(Serialize)]
+struct JsonFeedExtension<'a> {
+	color: Option<&'a str>,
+	extra: Option<&'a Json>,
+}
+
+pub fn generate_json_feed(channel: &Channel, feed: Feed) -> impl Reply {
+	let items = items(&feed, channel.limit)
+		.map(|entry| JsonFeedItem {
+			id: &entry.guid,
+			url: entry.link.as_deref(),
+			title: &entry.title,
+			content_text: entry.description.as_deref().unwrap_or_default(),
+			image: entry.image_url.as_deref(),
+			date_published: entry.timestamp.map(|ts| ts.to_rfc3339()),
+			tags: entry.feed_name.as_deref().into_iter().collect(),
+			notifier: JsonFeedExtension {
+				color: entry.color.as_deref(),
+				extra: entry.extra.as_ref(),
+			},
+		})
+		.collect();
+	
+	let body = JsonFeed {
+		version: "https://jsonfeed.org/version/1.1",
+		title: &channel.title,
+		feed_url: channel.link.as_deref(),
+		items,
+	};
+	
+	reply::with_header(reply::json(&body), "Content-Type", "application/feed+json; charset=UTF-8")
+}
@@ -0,0 +1,195 @@
+use chrono::{DateTime, Utc};
+use chrono::serde::ts_milliseconds_option;
+use serde::Serialize;
+use warp::{Filter, Rejection, Reply, reply, reject};
+use warp::http::StatusCode;
+
+use crate::state::State;
+use crate::providers::{ProviderStatus, FeedStatus};
+use crate::metrics;
+use super::InternalReject;
+use super::auth::Access;
+
+#[derive(Serialize)]
+struct Health {
+	status: &'static str,
+}
+
+#[derive(Serialize)]
+struct Status {
+	// First fetch is done
+	ready: bool,
+	#[serde(rename="fetchStarted", with = "ts_milliseconds_option")]
+	fetch_started: Option<DateTime<Utc>>,
+	#[serde(rename="fetchFinished", with = "ts_milliseconds_option")]
+	fetch_finished: Option<DateTime<Utc>>,
+	#[serde(rename="nextFetch", with = "ts_milliseconds_option")]
+	next_fetch: Option<DateTime<Utc>>,
+	providers: Vec<ProviderStatusInfo>,
+}
+
+// Times are milliseconds since epoch
+#[derive(Serialize)]
+struct FetchTimes {
+	#[serde(rename="lastFetchStart", with = "ts_milliseconds_option")]
+	last_fetch_start: Option<DateTime<Utc>>,
+	#[serde(rename="lastFetchEnd", with = "ts_milliseconds_option")]
+	last_fetch_end: Option<DateTime<Utc>>,
+	#[serde(rename="fetchDurationMs")]
+	fetch_duration_ms: Option<u64>,
+	#[serde(rename="nextFetch", with = "ts_milliseconds_option")]
+	next_fetch: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+struct ProviderStatusInfo {
+	name: String,
+	enabled: bool,
+	healthy: bool,
+	#[serde(flatten)]
+	times: FetchTimes,
+	// Entries of the feeds below
+	status: usize,
+	notifications: usize,
+	errors: usize,
+	#[serde(rename="consecutiveFailures")]
+	consecutive_failures: u32,
+	// Load error, or the newest error of the feeds below
+	#[serde(rename="lastError")]
+	last_error: Option<String>,
+	feeds: Vec<FeedStatusInfo>,
+}
+
+#[derive(Serialize)]
+struct FeedStatusInfo {
+	name: String,
+	#[serde(flatten)]
+	times: FetchTimes,
+	status: usize,
+	notifications: usize,
+	errors: usize,
+	#[serde(rename="consecutiveFailures")]
+	consecutive_failures: u32,
+	#[serde(rename="lastError")]
+	last_error: Option<String>,
+	#[serde(rename="lastErrorAt", with = "ts_milliseconds_option")]
+	last_error_at: Option<DateTime<Utc>>,
+}
+
+impl FetchTimes {
+	fn new(status: &ProviderStatus, next_fetch: Option<DateTime<Utc>>) -> Self {
+		FetchTimes {
+			last_fetch_start: status.last_fetch_start,
+			last_fetch_end: status.last_fetch,
+			fetch_duration_ms: status.fetch_duration.map(|duration| duration.as_millis() as u64),
+			next_fetch: status.enabled.then_some(next_fetch).flatten(),
+		}
+	}
+}
+
+fn feed_status_info(provider: &ProviderStatus, name: &str, status: &FeedStatus, next_fetch: Option<DateTime<Utc>>) -> FeedStatusInfo {
+	FeedStatusInfo {
+		name: name.to_string(),
+		times: FetchTimes::new(provider, next_fetch),
+		status: status.status,
+		notifications: status.notifications,
+		errors: status.errors,
+		consecutive_failures: status.consecutive_failures,
+		last_error: status.last_error.clone(),
+		last_error_at: status.last_error_at,
+	}
+}
+
+fn provider_status_info(name: &str, status: &ProviderStatus, access: &Access, next_fetch: Option<DateTime<Utc>>) -> ProviderStatusInfo {
+	let feeds = status.feed_statuses
+	                  .iter()
+	                  .filter(|(name, _)| access.can_read(name))
+	                  .map(|(name, feed)| feed_status_info(status, name, feed, next_fetch))
+	                  .collect::<Vec<_>>();
+	
+	// Errors of feeds outside of token's scopes are left out
+	let last_error = status.load_error
+	                       .clone()
+	                       .or_else(|| feeds.iter()
+	                                        .filter(|feed| feed.last_error.is_some())
+	                                        .max_by_key(|feed| feed.last_error_at)
+	                                        .and_then(|feed| feed.last_error.clone()));
+	
+	ProviderStatusInfo {
+		name: name.to_string(),
+		enabled: status.enabled,
+		healthy: status.is_healthy(),
+		times: FetchTimes::new(status, next_fetch),
+		status: feeds.iter().map(|feed| feed.status).sum(),
+		notifications: feeds.iter().map(|feed| feed.notifications).sum(),
+		errors: feeds.iter().map(|feed| feed.errors).sum(),
+		consecutive_failures: status.consecutive_failures,
+		last_error,
+		feeds,
+	}
+}
+
+// Process is up and serving requests
+async fn healthz() -> Result<impl Reply, Rejection> {
+	Ok(reply::json(&Health { status: "ok" }))
+}
+
+// Ready after the first fetch, until then feeds are empty
+async fn readyz(state: State) -> Result<impl Reply, Rejection> {
+	if state.fetch.load().finished.is_some() {
+		Ok(reply::with_status(reply::json(&Health { status: "ready" }), StatusCode::OK))
+	} else {
+		Ok(reply::with_status(reply::json(&Health { status: "starting" }), StatusCode::SERVICE_UNAVAILABLE))
+	}
+}
+
+async fn status(access: Access, state: State) -> Result<impl Reply, Rejection> {
+	access.require_read()?;
+	
+	let fetch = state.fetch.load();
+	let providers = state.providers
+	                     .load()
+	                     .iter()
+	                     .map(|(name, status)| provider_status_info(name, status, &access, fetch.next))
+	                     .collect();
+	
+	Ok(reply::json(&Status {
+		ready: fetch.finished.is_some(),
+		fetch_started: fetch.started,
+		fetch_finished: fetch.finished,
+		next_fetch: fetch.next,
+		providers,
+	}))
+}
+
+// Prometheus text format
+async fn metrics(access: Access) -> Result<impl Reply, Rejection> {
+	access.require_read()?;
+	
+	let body = metrics::gather(|feed| access.can_read(feed)).map_err(|err| reject::custom(InternalReject(err.to_string())))?;
+	
+	Ok(reply::with_header(body, "Content-Type", "text/plain; version=0.0.4; charset=utf-8"))
+}
+
+// Health checks don't need authentication, so they can be used by Docker and load balancers
+pub fn routes(state: State, auth: impl Filter<Extract = (Access,), Error = Rejection> + Clone + Send) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	let state = warp::any().map(move || state.clone());
+	
+	let healthz = warp::path!("healthz")
+	                   .and_then(healthz);
+	
+	let readyz = warp::path!("readyz")
+	                  .and(state.clone())
+	                  .and_then(readyz);
+	
+	let status = warp::path!("status")
+	                  .and(auth.clone())
+	                  .and(state)
+	                  .and_then(status);
+	
+	let metrics = warp::path!("metrics")
+	                   .and(auth)
+	                   .and_then(metrics);
+	
+	warp::get().and(healthz.or(readyz).or(status).or(metrics))
+}
@@ -0,0 +1,310 @@
+use std::cmp::Reverse;
+use std::convert::TryFrom;
+use chrono::{DateTime, Utc};
+use chrono::serde::ts_milliseconds_option;
+use serde::{Serialize, Deserialize};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+use super::Format;
+use crate::feeds::{Entry, Feed};
+use crate::utils::Map;
+
+#[derive(Deserialize)]
+pub struct FetchQuery {
+	pub filter: Option<String>,
+	pub flat: Option<bool>,
+	pub format: Option<Format>,
+	// Entries of these kinds only, all by default
+	kind: Option<Kinds>,
+	// Milliseconds since epoch, entries without timestamp are left out when set
+	since: Option<i64>,
+	until: Option<i64>,
+	// Case insensitive search in title and description
+	q: Option<String>,
+	// Entries after the one in the cursor, from the previous response
+	cursor: Option<EncodedCursor>,
+	offset: Option<usize>,
+	limit: Option<usize>,
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+	Status,
+	Notifications,
+	Errors,
+}
+
+impl Kind {
+	const ALL: [Kind; 3] = [Kind::Status, Kind::Notifications, Kind::Errors];
+	
+	fn entries(self, feed: &Feed) -> &Vec<Entry> {
+		match self {
+			Kind::Status => &feed.status,
+			Kind::Notifications => &feed.notifications,
+			Kind::Errors => &feed.errors,
+		}
+	}
+	
+	fn entries_mut(self, feed: &mut Feed) -> &mut Vec<Entry> {
+		match self {
+			Kind::Status => &mut feed.status,
+			Kind::Notifications => &mut feed.notifications,
+			Kind::Errors => &mut feed.errors,
+		}
+	}
+}
+
+// Comma separated, eg. "status,notifications"
+#[derive(Deserialize)]
+#[serde(try_from = "String")]
+struct Kinds(Vec<Kind>);
+
+impl TryFrom<String> for Kinds {
+	type Error = String;
+	
+	fn try_from(kinds: String) -> Result<Self, Self::Error> {
+		kinds.split(',')
+		     .map(|kind| match kind.trim() {
+			     "status" => Ok(Kind::Status),
+			     "notifications" => Ok(Kind::Notifications),
+			     "errors" => Ok(Kind::Errors),
+			     kind => Err(format!("Unknown kind {}, expected status, notifications or errors", kind)),
+		     })
+		     .collect::<Result<_, _>>()
+		     .map(Kinds)
+	}
+}
+
+// Same order as Entry's (without timestamp first, then newest to oldest), ties broken by feed name and guid
+type Key<'a> = (Option<Reverse<DateTime<Utc>>>, &'a str, &'a str);
+
+fn key<'a>(name: &'a str, entry: &'a Entry) -> Key<'a> {
+	(entry.timestamp.map(Reverse), name, &entry.guid)
+}
+
+// Last entry of a page, so following pages don't shift when feeds are fetched in between
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+	#[serde(rename="t", with = "ts_milliseconds_option")]
+	timestamp: Option<DateTime<Utc>>,
+	#[serde(rename="f")]
+	feed: String,
+	#[serde(rename="g")]
+	guid: String,
+}
+
+impl Cursor {
+	fn key(&self) -> Key<'_> {
+		(self.timestamp.map(Reverse), &self.feed, &self.guid)
+	}
+	
+	fn encode(&self) -> String {
+		URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+	}
+}
+
+// Base64 encoded JSON of the cursor, opaque for clients
+#[derive(Deserialize)]
+#[serde(try_from = "String")]
+struct EncodedCursor(Cursor);
+
+impl TryFrom<String> for EncodedCursor {
+	type Error = String;
+	
+	fn try_from(cursor: String) -> Result<Self, Self::Error> {
+		URL_SAFE_NO_PAD.decode(cursor)
+		               .ok()
+		               .and_then(|json| serde_json::from_slice(&json).ok())
+		               .map(EncodedCursor)
+		               .ok_or_else(|| "Invalid cursor".to_string())
+	}
+}
+
+impl FetchQuery {
+	fn matches(&self, entry: &Entry, q: Option<&str>) -> bool {
+		let timestamp = entry.timestamp.map(|ts| ts.timestamp_millis());
+		
+		self.since.is_none_or(|since| timestamp.is_some_and(|ts| ts >= since)) &&
+		self.until.is_none_or(|until| timestamp.is_some_and(|ts| ts < until)) &&
+		q.is_none_or(|q| entry.title.to_lowercase().contains(q) ||
+		                 entry.description.as_ref().is_some_and(|description| description.to_lowercase().contains(q)))
+	}
+	
+	// Matching entries of given feeds, merged and ordered newest first
+	pub fn select<'a>(&self, feeds: impl Iterator<Item = (&'a String, &'a Feed)>) -> Page<'a> {
+		let kinds = self.kind.as_ref().map_or(&Kind::ALL[..], |kinds| &kinds.0);
+		let q = self.q.as_ref().map(|q| q.to_lowercase());
+		
+		let mut names = Vec::new();
+		let mut entries = Vec::new();
+		
+		for (name, feed) in feeds {
+			names.push(name);
+			
+			for &kind in kinds {
+				entries.extend(kind.entries(feed)
+				                   .iter()
+				                   .filter(|entry| self.matches(entry, q.as_deref()))
+				                   .map(|entry| (kind, name, entry)));
+			}
+		}
+		
+		entries.sort_by(|(_, a_name, a), (_, b_name, b)| key(a_name, a).cmp(&key(b_name, b)));
+		
+		// One more than needed, to tell if there is a next page
+		let limit = self.limit.unwrap_or(usize::MAX);
+		let mut entries: Vec<_> = entries.into_iter()
+		                                 .skip_while(|(_, name, entry)| self.cursor.as_ref().is_some_and(|cursor| key(name, entry) <= cursor.0.key()))
+		                                 .skip(self.offset.unwrap_or(0))
+		                                 .take(limit.saturating_add(1))
+		                                 .collect();
+		
+		let next = if entries.len() > limit {
+			entries.truncate(limit);
+			entries.last().map(|(_, name, entry)| Cursor {
+				timestamp: entry.timestamp,
+				feed: name.to_string(),
+				guid: entry.guid.clone(),
+			}.encode())
+		} else {
+			None
+		};
+		
+		Page { names, entries, next }
+	}
+}
+
+pub struct Page<'a> {
+	names: Vec<&'a String>,
+	entries: Vec<(Kind, &'a String, &'a Entry)>,
+	// Cursor of the next page, if there is one
+	pub next: Option<String>,
+}
+
+impl<'a> Page<'a> {
+	pub fn names(&self) -> impl Iterator<Item = String> + '_ {
+		self.names.iter().map(|name| name.to_string())
+	}
+	
+	// All entries in single feed
+	pub fn feed(&self) -> Feed {
+		let mut feed = Feed::new();
+		
+		for (kind, _, entry) in &self.entries {
+			kind.entries_mut(&mut feed).push((*entry).clone());
+		}
+		
+		feed
+	}
+	
+	// Every selected feed, even without any matching entries
+	pub fn feeds(&self) -> Map<Feed> {
+		let mut feeds: Map<Feed> = self.names.iter().map(|name| (name.to_string(), Feed::new())).collect();
+		
+		for (kind, name, entry) in &self.entries {
+			if let Some(feed) = feeds.get_mut(*name) {
+				kind.entries_mut(feed).push((*entry).clone());
+			}
+		}
+		
+		feeds
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use chrono::TimeZone;
+	use serde_json::json;
+	use super::*;
+	use crate::utils::Json;
+	
+	fn query(query: Json) -> FetchQuery {
+		serde_json::from_value(query).unwrap()
+	}
+	
+	fn entry(title: &str, minute: u32) -> Entry {
+		Entry::new(title, title).timestamp(Utc.with_ymd_and_hms(2024, 1, 1, 12, minute, 0).unwrap())
+	}
+	
+	fn feeds() -> Map<Feed> {
+		let mut a = Feed::new();
+		a.notifications = vec![entry("a1", 1), entry("a3", 3), entry("a5", 5)];
+		a.status = vec![Entry::new("status", "status")];
+		
+		let mut b = Feed::new();
+		b.notifications = vec![entry("b2", 2), entry("b4", 4)];
+		b.errors = vec![entry("error", 6)];
+		
+		vec![("a".to_string(), a), ("b".to_string(), b)].into_iter().collect()
+	}
+	
+	fn titles(page: &Page) -> Vec<String> {
+		page.entries.iter().map(|(_, _, entry)| entry.title.clone()).collect()
+	}
+	
+	#[test]
+	fn orders_entries_newest_first() {
+		let feeds = feeds();
+		let page = query(json!({})).select(feeds.iter());
+		
+		assert_eq!(titles(&page), ["status", "error", "a5", "b4", "a3", "b2", "a1"]);
+		assert_eq!(page.next, None);
+	}
+	
+	#[test]
+	fn follows_cursor_to_last_page() {
+		let feeds = feeds();
+		let mut cursor: Option<String> = None;
+		let mut pages = vec![];
+		
+		loop {
+			let page = query(json!({ "limit": 3, "cursor": cursor })).select(feeds.iter());
+			pages.push(titles(&page));
+			cursor = page.next;
+			if cursor.is_none() { break }
+		}
+		
+		assert_eq!(pages, [vec!["status", "error", "a5"], vec!["b4", "a3", "b2"], vec!["a1"]]);
+	}
+	
+	#[test]
+	fn cursor_ignores_newer_entries() {
+		let mut feeds = feeds();
+		let first = query(json!({ "limit": 3 })).select(feeds.iter()).next.unwrap();
+		
+		feeds.get_mut("b").unwrap().notifications.push(entry("b9", 9));
+		
+		let page = query(json!({ "limit": 3, "cursor": first })).select(feeds.iter());
+		assert_eq!(titles(&page), ["b4", "a3", "b2"]);
+	}
+	
+	#[test]
+	fn filters_entries() {
+		let feeds = feeds();
+		let since = Utc.with_ymd_and_hms(2024, 1, 1, 12, 2, 0).unwrap().timestamp_millis();
+		let until = Utc.with_ymd_and_hms(2024, 1, 1, 12, 5, 0).unwrap().timestamp_millis();
+		
+		assert_eq!(titles(&query(json!({ "since": since, "until": until })).select(feeds.iter())), ["b4", "a3", "b2"]);
+		assert_eq!(titles(&query(json!({ "kind": "errors, status" })).select(feeds.iter())), ["status", "error"]);
+		assert_eq!(titles(&query(json!({ "q": "A" })).select(feeds.iter())), ["status", "a5", "a3", "a1"]);
+		assert_eq!(titles(&query(json!({ "offset": 5, "limit": 1 })).select(feeds.iter())), ["b2"]);
+	}
+	
+	#[test]
+	fn keeps_feeds_without_matches() {
+		let feeds = feeds();
+		let page = query(json!({ "kind": "errors" })).select(feeds.iter());
+		let feeds = page.feeds();
+		
+		assert_eq!(feeds.keys().collect::<Vec<_>>(), ["a", "b"]);
+		assert!(feeds["a"].errors.is_empty());
+		assert_eq!(feeds["b"].errors.len(), 1);
+	}
+	
+	#[test]
+	fn rejects_invalid_input() {
+		assert!(serde_json::from_value::<FetchQuery>(json!({ "cursor": "not a cursor" })).is_err());
+		assert!(serde_json::from_value::<FetchQuery>(json!({ "kind": "status,unknown" })).is_err());
+	}
+}
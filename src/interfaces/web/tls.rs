@@ -0,0 +1,139 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use arc_swap::ArcSwap;
+use futures::{Stream, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::crypto::ring::sign::any_supported_type;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{debug, info, warn, error};
+use anyhow::Result;
+use thiserror::Error;
+
+// Clients which don't finish the handshake in time are dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+// Certificate is swapped when files change, so renewed certificates are used without restart
+#[derive(Debug)]
+struct ReloadingResolver {
+	key: ArcSwap<CertifiedKey>,
+}
+
+impl ResolvesServerCert for ReloadingResolver {
+	fn resolve(&self, _: ClientHello) -> Option<Arc<CertifiedKey>> {
+		Some(self.key.load_full())
+	}
+}
+
+async fn load_key(cert_path: &Path, key_path: &Path) -> Result<CertifiedKey> {
+	let cert = tokio::fs::read(cert_path).await?;
+	let certs = rustls_pemfile::certs(&mut &*cert).collect::<Result<Vec<_>, _>>()?;
+	if certs.is_empty() { return Err(MissingCertificateError.into()) }
+	
+	let key = tokio::fs::read(key_path).await?;
+	let key = rustls_pemfile::private_key(&mut &*key)?.ok_or(MissingKeyError)?;
+	
+	Ok(CertifiedKey::new(certs, any_supported_type(&key)?))
+}
+
+async fn modified(cert_path: &Path, key_path: &Path) -> Option<(SystemTime, SystemTime)> {
+	let cert = tokio::fs::metadata(cert_path).await.ok()?.modified().ok()?;
+	let key = tokio::fs::metadata(key_path).await.ok()?.modified().ok()?;
+	Some((cert, key))
+}
+
+pub struct Tls {
+	acceptor: TlsAcceptor,
+	resolver: Arc<ReloadingResolver>,
+	cert_path: PathBuf,
+	key_path: PathBuf,
+}
+
+impl Tls {
+	pub async fn load(cert_path: PathBuf, key_path: PathBuf) -> Result<Self> {
+		let resolver = Arc::new(ReloadingResolver {
+			key: ArcSwap::from_pointee(load_key(&cert_path, &key_path).await?),
+		});
+		
+		let config = ServerConfig::builder()
+		                          .with_no_client_auth()
+		                          .with_cert_resolver(resolver.clone());
+		
+		Ok(Tls {
+			acceptor: TlsAcceptor::from(Arc::new(config)),
+			resolver,
+			cert_path,
+			key_path,
+		})
+	}
+	
+	// Checks files periodically, broken files are reported and the previous certificate is kept
+	pub async fn reload_loop(&self) {
+		let mut last_modified = modified(&self.cert_path, &self.key_path).await;
+		let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+		
+		loop {
+			interval.tick().await;
+			
+			let current = modified(&self.cert_path, &self.key_path).await;
+			if current.is_none() || current == last_modified { continue }
+			last_modified = current;
+			
+			match load_key(&self.cert_path, &self.key_path).await {
+				Ok(key) => {
+					self.resolver.key.store(Arc::new(key));
+					info!(cert = %self.cert_path.to_string_lossy(), "Reloaded TLS certificate");
+				},
+				Err(err) => error!(error = %err, "Unable to reload TLS certificate"),
+			}
+		}
+	}
+	
+	// Handshakes are done in separate tasks, so slow clients don't block others
+	pub fn incoming(&self, listener: TcpListener) -> impl Stream<Item = io::Result<TlsStream<TcpStream>>> {
+		let (sender, receiver) = mpsc::channel(16);
+		let acceptor = self.acceptor.clone();
+		
+		tokio::spawn(async move {
+			loop {
+				let stream = match listener.accept().await {
+					Ok((stream, _)) => stream,
+					Err(err) => {
+						warn!(error = %err, "Unable to accept connection");
+						tokio::time::sleep(Duration::from_secs(1)).await;
+						continue;
+					},
+				};
+				
+				let acceptor = acceptor.clone();
+				let sender = sender.clone();
+				
+				tokio::spawn(async move {
+					match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+						Ok(Ok(stream)) => { let _ = sender.send(stream).await; },
+						Ok(Err(err)) => debug!(error = %err, "TLS handshake failed"),
+						Err(_) => debug!("TLS handshake timed out"),
+					}
+				});
+			}
+		});
+		
+		ReceiverStream::new(receiver).map(Ok)
+	}
+}
+
+#[derive(Debug, Copy, Clone, Error)]
+#[error("No certificates found in tlsCert")]
+pub struct MissingCertificateError;
+
+#[derive(Debug, Copy, Clone, Error)]
+#[error("No private key found in tlsKey")]
+pub struct MissingKeyError;
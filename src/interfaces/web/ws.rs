@@ -0,0 +1,73 @@
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use chrono::serde::ts_milliseconds;
+use serde::Serialize;
+use warp::{Filter, Rejection, Reply};
+use warp::ws::{Message, WebSocket, Ws};
+use futures::{future, SinkExt, StreamExt};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::error;
+
+use super::auth::Access;
+use crate::feeds::Entry;
+use crate::state::State;
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Push<'a> {
+	// Sent after every fetch, with new entries of feeds the token can read
+	Update {
+		#[serde(with = "ts_milliseconds")]
+		created: DateTime<Utc>,
+		entries: Vec<&'a Entry>,
+	},
+}
+
+// GET /ws, browsers can't set headers on websockets, so tokens are passed in ?token= query
+pub fn route(state: State, auth: impl Filter<Extract = (Access,), Error = Rejection> + Clone + Send) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path("ws")
+	     .and(warp::path::end())
+	     .and(auth)
+	     .and(warp::ws())
+	     .and_then(move |access: Access, ws: Ws| {
+		     let state = state.clone();
+		     
+		     future::ready(access.require_read().map(|_| ws.on_upgrade(move |socket| handle_socket(socket, state, access))))
+	     })
+}
+
+async fn handle_socket(socket: WebSocket, state: State, access: Access) {
+	let (mut sender, mut receiver) = socket.split();
+	let mut updates = state.new_entries.subscribe();
+	
+	loop {
+		tokio::select! {
+			// Nothing is expected from clients, pings and closing are handled by warp
+			message = receiver.next() => match message {
+				Some(Ok(_)) => {},
+				Some(Err(_)) | None => return,
+			},
+			received = updates.recv() => {
+				let entries: Arc<Vec<Entry>> = match received {
+					Ok(entries) => entries,
+					Err(RecvError::Lagged(_)) => continue,
+					Err(RecvError::Closed) => return,
+				};
+				
+				let update = Push::Update {
+					created: state.feeds.load().created,
+					entries: entries.iter()
+					                .filter(|entry| entry.feed_name.as_ref().is_some_and(|name| access.can_read(name)))
+					                .collect(),
+				};
+				
+				let message = match serde_json::to_string(&update) {
+					Ok(message) => message,
+					Err(err) => { error!(error = %err, "Unable to serialize websocket update"); continue },
+				};
+				
+				if sender.send(Message::text(message)).await.is_err() { return }
+			},
+		}
+	}
+}
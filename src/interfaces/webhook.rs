@@ -0,0 +1,305 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use regex::{Regex, RegexBuilder};
+use chrono::{DateTime, Utc};
+use futures::future;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast::error::RecvError;
+use anyhow::Result;
+use thiserror::Error;
+use tracing::{info, warn, error};
+
+use crate::utils::{Json, Map};
+use crate::state::State;
+use crate::feeds::Entry;
+
+#[derive(Deserialize)]
+struct WebhookConfig {
+	targets: Vec<TargetConfig>,
+	// Relative to the data directory
+	#[serde(rename="deadLetter", default = "default_dead_letter")]
+	dead_letter: String,
+}
+
+fn default_dead_letter() -> String {
+	"webhook-dead-letter.jsonl".to_string()
+}
+
+#[derive(Deserialize, Clone)]
+struct TargetConfig {
+	url: String,
+	#[serde(default)]
+	format: Format,
+	// Overrides body generated by format, strings can contain {{field}} placeholders
+	template: Option<Json>,
+	// Regex, only entries of matching feeds are sent
+	filter: Option<String>,
+	#[serde(default)]
+	headers: Map<String>,
+	#[serde(default = "default_retries")]
+	retries: u32,
+	#[serde(rename="backoffSecs", default = "default_backoff_secs")]
+	backoff_secs: u64,
+}
+
+fn default_retries() -> u32 { 5 }
+fn default_backoff_secs() -> u64 { 2 }
+
+#[derive(Deserialize, Copy, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+	#[default]
+	Json,
+	Discord,
+	Slack,
+	Ntfy,
+	Gotify,
+}
+
+#[derive(Serialize)]
+struct DeadLetter<'a> {
+	time: DateTime<Utc>,
+	url: &'a str,
+	error: String,
+	body: &'a Json,
+}
+
+struct Target {
+	url: String,
+	template: Json,
+	filter: Option<Regex>,
+	headers: Map<String>,
+	retries: u32,
+	backoff: Duration,
+}
+
+impl Target {
+	fn new(config: TargetConfig) -> Result<Self> {
+		let filter = config.filter
+		                   .map(|filter| RegexBuilder::new(&filter)
+		                                              .size_limit(1024 * 32)
+		                                              .dfa_size_limit(1024 * 32)
+		                                              .nest_limit(10)
+		                                              .case_insensitive(true)
+		                                              .build())
+		                   .transpose()?;
+		
+		let mut url = config.url;
+		let template = match config.template {
+			Some(template) => template,
+			None => match config.format {
+				Format::Json => json!({
+					"title": "{{title}}",
+					"guid": "{{guid}}",
+					"feedName": "{{feedName}}",
+					"description": "{{description}}",
+					"link": "{{link}}",
+					"color": "{{color}}",
+					"imageURL": "{{imageURL}}",
+					"timestamp": "{{timestamp}}",
+					"extra": "{{extra}}",
+				}),
+				Format::Discord => json!({
+					"username": "rust-notifier",
+					"embeds": [{
+						"title": "{{title}}",
+						"description": "{{description}}",
+						"url": "{{link}}",
+						"thumbnail": { "url": "{{imageURL}}" },
+						"footer": { "text": "{{feedName}}" },
+					}],
+				}),
+				Format::Slack => json!({
+					"text": "*{{feedName}}*: <{{link}}|{{title}}>\n{{description}}",
+				}),
+				Format::Ntfy => {
+					// ntfy expects JSON messages to be published to the root URL, with topic in the body
+					let (root, topic) = url.trim_end_matches('/')
+					                       .rsplit_once('/')
+					                       .map(|(root, topic)| (root.to_string(), topic.to_string()))
+					                       .ok_or(InvalidUrlError)?;
+					url = root;
+					json!({
+						"topic": topic,
+						"title": "{{title}}",
+						"message": "{{description}}",
+						"click": "{{link}}",
+						"attach": "{{imageURL}}",
+						"tags": ["{{feedName}}"],
+					})
+				},
+				Format::Gotify => json!({
+					"title": "{{title}}",
+					"message": "{{description}}",
+					"extras": {
+						"client::notification": { "click": { "url": "{{link}}" } },
+						"client::display": { "contentType": "text/plain" },
+					},
+				}),
+			},
+		};
+		
+		Ok(Target {
+			url,
+			template,
+			filter,
+			headers: config.headers,
+			retries: config.retries,
+			backoff: Duration::from_secs(config.backoff_secs),
+		})
+	}
+	
+	fn matches(&self, entry: &Entry) -> bool {
+		self.filter.as_ref().is_none_or(|filter| entry.feed_name.as_ref().is_some_and(|name| filter.is_match(name)))
+	}
+	
+	async fn send(&self, client: &reqwest::Client, body: &Json) -> Result<(), (bool, anyhow::Error)> {
+		let mut request = client.post(&self.url).json(body);
+		for (key, value) in self.headers.iter() {
+			request = request.header(key, value);
+		}
+		
+		let response = request.send().await.map_err(|err| (true, err.into()))?;
+		let status = response.status();
+		
+		match response.error_for_status() {
+			Ok(_) => Ok(()),
+			// Client errors won't go away on their own, except rate limiting
+			Err(err) => Err((status.is_server_error() || status.as_u16() == 429, err.into())),
+		}
+	}
+	
+	// Returns error after all retries failed
+	async fn deliver(&self, client: &reqwest::Client, body: &Json) -> Result<()> {
+		let mut attempt = 0;
+		
+		loop {
+			match self.send(client, body).await {
+				Ok(()) => return Ok(()),
+				Err((retry, err)) if !retry || attempt >= self.retries => return Err(err),
+				Err(_) => {
+					tokio::time::sleep(self.backoff * 2u32.saturating_pow(attempt)).await;
+					attempt += 1;
+				},
+			}
+		}
+	}
+}
+
+fn placeholder(entry: &Entry, name: &str) -> Option<Json> {
+	Some(match name {
+		"title" => Json::from(entry.title.clone()),
+		"guid" => Json::from(entry.guid.clone()),
+		"feedName" => Json::from(entry.feed_name.clone()),
+		"description" => Json::from(entry.description.clone()),
+		"link" => Json::from(entry.link.clone()),
+		"color" => Json::from(entry.color.clone()),
+		"imageURL" => Json::from(entry.image_url.clone()),
+		"timestamp" => Json::from(entry.timestamp.map(|ts| ts.timestamp_millis())),
+		"extra" => entry.extra.clone().unwrap_or(Json::Null),
+		_ => return None,
+	})
+}
+
+// Strings consisting of a single placeholder are replaced by its JSON value, others are interpolated
+fn render(template: &Json, entry: &Entry) -> Json {
+	match template {
+		Json::String(text) => {
+			if let Some(value) = text.strip_prefix("{{")
+			                         .and_then(|text| text.strip_suffix("}}"))
+			                         .filter(|name| !name.contains("{{"))
+			                         .and_then(|name| placeholder(entry, name)) {
+				return value;
+			}
+			
+			let mut result = String::new();
+			let mut rest = text.as_str();
+			while let Some((before, after)) = rest.split_once("{{") {
+				result += before;
+				match after.split_once("}}") {
+					Some((name, after)) => {
+						match placeholder(entry, name) {
+							Some(Json::String(value)) => result += &value,
+							Some(Json::Null) => {},
+							Some(value) => result += &value.to_string(),
+							None => { result += "{{"; result += name; result += "}}" },
+						}
+						rest = after;
+					},
+					None => { result += "{{"; rest = after },
+				}
+			}
+			result += rest;
+			
+			Json::String(result)
+		},
+		Json::Array(values) => Json::Array(values.iter().map(|value| render(value, entry)).collect()),
+		Json::Object(values) => Json::Object(values.iter().map(|(key, value)| (key.clone(), render(value, entry))).collect()),
+		value => value.clone(),
+	}
+}
+
+async fn write_dead_letter(path: &Path, letter: &DeadLetter<'_>) -> Result<()> {
+	if let Some(dir) = path.parent() {
+		tokio::fs::create_dir_all(dir).await?;
+	}
+	
+	let mut line = serde_json::to_vec(letter)?;
+	line.push(b'\n');
+	
+	let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?;
+	file.write_all(&line).await?;
+	
+	Ok(())
+}
+
+async fn run_target(target: Target, state: State, dead_letter: Arc<PathBuf>) {
+	let client = reqwest::Client::new();
+	let mut receiver = state.new_entries.subscribe();
+	
+	loop {
+		let entries = match receiver.recv().await {
+			Ok(entries) => entries,
+			Err(RecvError::Lagged(skipped)) => {
+				warn!(url = %target.url, skipped, "Webhook skipped updates");
+				continue
+			},
+			Err(RecvError::Closed) => return,
+		};
+		
+		for entry in entries.iter().filter(|entry| target.matches(entry)) {
+			let body = render(&target.template, entry);
+			
+			if let Err(err) = target.deliver(&client, &body).await {
+				error!(url = %target.url, error = %err, "Unable to deliver webhook");
+				
+				let letter = DeadLetter { time: Utc::now(), url: &target.url, error: err.to_string(), body: &body };
+				if let Err(err) = write_dead_letter(&dead_letter, &letter).await {
+					error!(error = %err, "Unable to write webhook dead letter");
+				}
+			}
+		}
+	}
+}
+
+pub async fn serve(config: Json, state: State, data_dir: PathBuf) -> Result<()> {
+	let config: WebhookConfig = serde_json::from_value(config)?;
+	let dead_letter = Arc::new(data_dir.join(config.dead_letter));
+	
+	let targets = config.targets
+	                    .into_iter()
+	                    .map(Target::new)
+	                    .collect::<Result<Vec<_>>>()?;
+	
+	info!("Sending webhooks to {} targets", targets.len());
+	future::join_all(targets.into_iter().map(|target| run_target(target, state.clone(), dead_letter.clone()))).await;
+	
+	Ok(())
+}
+
+#[derive(Debug, Copy, Clone, Error)]
+#[error("ntfy URL should include the topic, eg. https://ntfy.sh/my-topic")]
+pub struct InvalidUrlError;
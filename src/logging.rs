@@ -0,0 +1,23 @@
+use std::io::{self, IsTerminal};
+use anyhow::{anyhow, Result};
+use tracing_subscriber::EnvFilter;
+
+use crate::config::{LogConfig, LogFormat};
+
+// Logs go to stderr, so they don't mix with output of CLI commands
+pub fn init(config: &LogConfig) -> Result<()> {
+	let filter = match EnvFilter::try_from_default_env() {
+		Ok(filter) => filter,
+		Err(_) => EnvFilter::try_new(&config.level)?,
+	};
+	
+	let builder = tracing_subscriber::fmt()
+	                                 .with_env_filter(filter)
+	                                 .with_ansi(io::stderr().is_terminal())
+	                                 .with_writer(io::stderr);
+	
+	match config.format {
+		LogFormat::Text => builder.try_init(),
+		LogFormat::Json => builder.json().flatten_event(true).try_init(),
+	}.map_err(|err| anyhow!(err))
+}
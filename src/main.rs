@@ -6,21 +6,27 @@
 #![feature(duration_constructors)]
 
 use std::env;
+use std::path::Path;
 use std::time::Duration;
 use getopts::Options;
 use futures::future;
 use anyhow::Result;
+use thiserror::Error;
 
 mod utils;
 mod feeds;
 mod config;
 use config::Config;
+mod logging;
 mod providers;
 use providers::Providers;
 mod interfaces;
 use interfaces::Interfaces;
 mod state;
+mod metrics;
 use state::State;
+mod cli;
+use cli::Format;
 
 
 #[tokio::main]
@@ -30,6 +36,13 @@ pub async fn main() -> Result<()> {
 	let mut opts = Options::new();
 	
 	opts.optopt("c", "config", "Select fallback device to use", "config.json");
+	opts.optopt("f", "feed", "Feed to fetch (fetch)", "NAME");
+	opts.optopt("", "format", "Output format, json or table (fetch, list)", "FORMAT");
+	opts.optopt("", "url", "URL of the web interface (list)", "URL");
+	opts.optopt("", "token", "Token of the web interface, the first one with read scope in config by default (list)", "TOKEN");
+	opts.optopt("p", "provider", "Provider of the new feed (add-feed)", "PROVIDER");
+	opts.optopt("d", "data", "Provider data of the new feed, JSON or string (add-feed)", "DATA");
+	opts.optopt("", "color", "Color of the new feed (add-feed)", "COLOR");
 	opts.optflag("h", "help", "Print this help menu");
 	
 	let matches = opts.parse(&args[1..])?;
@@ -39,17 +52,44 @@ pub async fn main() -> Result<()> {
 		return Ok(());
 	}
 	
-	let config = matches.opt_get("c")?
-	                    .unwrap_or("config.json".to_string());
+	let config_path = matches.opt_get("c")?
+	                         .unwrap_or("config.json".to_string());
+	let config_path = Path::new(&config_path);
+	let command = matches.free.first().map(String::as_str).unwrap_or("serve");
+	let argument = matches.free.get(1).ok_or(MissingArgumentError);
+	let format = matches.opt_get::<Format>("format")?;
 	
-	println!("Loading config from {config}...");
-	let config = Config::load(config).await?;
-	println!("Config Loaded");
+	match command {
+		"serve" => serve(config_path).await,
+		"fetch" => {
+			let feed = matches.opt_str("f").ok_or(MissingArgumentError)?;
+			let config = Config::load(config_path).await?;
+			logging::init(&config.log)?;
+			cli::fetch(config, &feed, format.unwrap_or(Format::Json)).await
+		},
+		"list" => cli::list(Config::load(config_path).await?, matches.opt_str("url"), matches.opt_str("token"), format.unwrap_or(Format::Table)).await,
+		"validate-config" => cli::validate_config(config_path).await,
+		"add-feed" => {
+			let provider = matches.opt_str("p").ok_or(MissingArgumentError)?;
+			cli::add_feed(config_path, argument?, provider, matches.opt_str("d"), matches.opt_str("color")).await
+		},
+		"remove-feed" => cli::remove_feed(config_path, argument?).await,
+		_ => {
+			print_usage(&program, opts);
+			Err(UnknownCommandError.into())
+		},
+	}
+}
+
+async fn serve(config_path: &Path) -> Result<()> {
+	let config = Config::load(config_path).await?;
+	logging::init(&config.log)?;
+	tracing::info!(path = %config_path.to_string_lossy(), "Config loaded");
 	
-	let mut providers = Providers::new(config.providers);
-	let interfaces = Interfaces::new(config.interfaces);
-	let state = State::new(config.feeds);
+	let mut providers = Providers::new(config.providers, config.data_dir.clone().into());
+	let interfaces = Interfaces::new(config.interfaces, config.data_dir.into());
 	let fetch_interval = Duration::from_secs(config.fetch_interval_secs);
+	let state = State::new(config.feeds, config_path.to_path_buf(), fetch_interval);
 	
 	future::try_join(providers.fetch_loop(state.clone(), fetch_interval),
 	                 interfaces.serve(state.clone())).await?;
@@ -58,6 +98,21 @@ pub async fn main() -> Result<()> {
 }
 
 fn print_usage(program: &str, opts: Options) {
-	let brief = format!("Usage: {} [options]", program);
+	let brief = format!("Usage: {} [options] [command]\n\n\
+	                     Commands:\n\
+	                     \x20   serve               Fetch feeds and serve interfaces (default)\n\
+	                     \x20   fetch --feed NAME   Fetch single feed once and print it\n\
+	                     \x20   list                List feeds of running instance, using its web interface\n\
+	                     \x20   validate-config     Check config for errors\n\
+	                     \x20   add-feed NAME       Add feed to config, requires --provider\n\
+	                     \x20   remove-feed NAME    Remove feed from config", program);
 	print!("{}", opts.usage(&brief));
 }
+
+#[derive(Debug, Copy, Clone, Error)]
+#[error("Unknown command")]
+pub struct UnknownCommandError;
+
+#[derive(Debug, Copy, Clone, Error)]
+#[error("Missing argument, see --help")]
+pub struct MissingArgumentError;
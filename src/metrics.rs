@@ -0,0 +1,142 @@
+use std::future::Future;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+use prometheus::{HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Encoder, TextEncoder};
+use prometheus::{register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge_vec};
+use reqwest::{RequestBuilder, Response};
+use tracing::{debug, debug_span, Instrument};
+use anyhow::Result;
+
+use crate::feeds::Feeds;
+
+// Metrics are global, so providers can record them without access to the state
+
+static FETCH_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| register_histogram_vec!(
+	"notifier_fetch_duration_seconds",
+	"Duration of fetching all feeds of a provider",
+	&["provider"],
+	vec![0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0]
+).unwrap());
+
+static UPSTREAM_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| register_int_counter_vec!(
+	"notifier_upstream_requests_total",
+	"HTTP requests sent by providers, status is \"error\" if no response was received",
+	&["host", "status"]
+).unwrap());
+
+static UPSTREAM_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| register_histogram_vec!(
+	"notifier_upstream_request_duration_seconds",
+	"Time until response headers of HTTP requests sent by providers",
+	&["host"]
+).unwrap());
+
+static FEED_ENTRIES: LazyLock<IntGaugeVec> = LazyLock::new(|| register_int_gauge_vec!(
+	"notifier_feed_entries",
+	"Entries of feeds in the last fetch",
+	&["feed", "kind"]
+).unwrap());
+
+static FEED_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| register_int_counter_vec!(
+	"notifier_feed_errors_total",
+	"Error entries of feeds, summed over all fetches",
+	&["feed"]
+).unwrap());
+
+static YOUTUBE_QUOTA: LazyLock<IntCounter> = LazyLock::new(|| register_int_counter!(
+	"notifier_youtube_quota_units_total",
+	"YouTube Data API quota units used, quota resets daily at midnight Pacific Time"
+).unwrap());
+
+static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| register_int_counter_vec!(
+	"notifier_http_requests_total",
+	"Requests handled by the web interface",
+	&["route", "method", "status"]
+).unwrap());
+
+static HTTP_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| register_histogram_vec!(
+	"notifier_http_request_duration_seconds",
+	"Time until response headers of requests handled by the web interface",
+	&["route"]
+).unwrap());
+
+pub fn record_fetch(provider: &str, duration: Duration) {
+	FETCH_DURATION.with_label_values(&[provider]).observe(duration.as_secs_f64());
+}
+
+// Feeds removed from config disappear from the gauges too
+pub fn record_feeds(feeds: &Feeds) {
+	FEED_ENTRIES.reset();
+	
+	for (name, feed) in feeds.iter() {
+		FEED_ENTRIES.with_label_values(&[name, "status"]).set(feed.status.len() as i64);
+		FEED_ENTRIES.with_label_values(&[name, "notifications"]).set(feed.notifications.len() as i64);
+		FEED_ENTRIES.with_label_values(&[name, "errors"]).set(feed.errors.len() as i64);
+		FEED_ERRORS.with_label_values(&[name]).inc_by(feed.errors.len() as u64);
+	}
+}
+
+pub fn record_youtube_quota(units: u64) {
+	YOUTUBE_QUOTA.inc_by(units);
+}
+
+pub fn record_http_request(route: &str, method: &str, status: u16, duration: Duration) {
+	HTTP_REQUESTS.with_label_values(&[route, method, &status.to_string()]).inc();
+	HTTP_DURATION.with_label_values(&[route]).observe(duration.as_secs_f64());
+}
+
+pub trait RequestBuilderEx {
+	// Same as send, but records status and duration of the request by host, and logs it in its own span
+	fn send_metered(self) -> impl Future<Output = reqwest::Result<Response>>;
+}
+
+impl RequestBuilderEx for RequestBuilder {
+	fn send_metered(self) -> impl Future<Output = reqwest::Result<Response>> {
+		let (client, request) = self.build_split();
+		
+		async move {
+			let request = request?;
+			let host = request.url().host_str().unwrap_or_default().to_string();
+			let span = debug_span!(target: "upstream", "request", method = %request.method(), url = %request.url());
+			
+			async move {
+				let started = Instant::now();
+				let result = client.execute(request).await;
+				let duration = started.elapsed();
+				
+				let status = match &result {
+					Ok(response) => response.status().as_u16().to_string(),
+					Err(_) => "error".to_string(),
+				};
+				
+				match &result {
+					Ok(_) => debug!(target: "upstream", %status, duration_ms = duration.as_millis() as u64, "Upstream request"),
+					Err(err) => debug!(target: "upstream", error = %err, duration_ms = duration.as_millis() as u64, "Upstream request failed"),
+				}
+				
+				UPSTREAM_REQUESTS.with_label_values(&[&host, &status]).inc();
+				UPSTREAM_DURATION.with_label_values(&[&host]).observe(duration.as_secs_f64());
+				
+				result
+			}.instrument(span).await
+		}
+	}
+}
+
+// Prometheus text format. Series of feeds which can't be read are left out
+pub fn gather(can_read: impl Fn(&str) -> bool) -> Result<String> {
+	// Registered on first use, exposed as zero until then
+	LazyLock::force(&YOUTUBE_QUOTA);
+	
+	let mut families = prometheus::gather();
+	
+	for family in families.iter_mut() {
+		family.mut_metric().retain(|metric| metric.get_label().iter().all(|label| label.get_name() != "feed" || can_read(label.get_value())));
+	}
+	
+	// Encoder doesn't accept families without any series
+	families.retain(|family| !family.get_metric().is_empty());
+	
+	let mut buffer = Vec::new();
+	TextEncoder::new().encode(&families, &mut buffer)?;
+	Ok(String::from_utf8(buffer)?)
+}
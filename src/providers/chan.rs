@@ -14,6 +14,7 @@ use thiserror::Error;
 use crate::utils::{Map, Json, IteratorEx, hash};
 use crate::feeds::{Feed, Entry};
 use crate::config::ConfigFeedEntry;
+use crate::metrics::RequestBuilderEx;
 use super::Provider;
 
 const MAX_CON_REQUESTS: usize = 1;
@@ -27,7 +28,7 @@ impl ChanProvider {
 }
 
 #[derive(Deserialize)]
-struct ProviderData {
+pub(super) struct ProviderData {
 	boards: Vec<String>,
 	filter: String,
 }
@@ -86,7 +87,7 @@ impl Provider for ChanProvider {
 				                                       .get(&url)
 				                                       .header(header::IF_MODIFIED_SINCE, last_fetch.to_rfc2822())
 				                                       .header(header::USER_AGENT, format!("rust-notifier/{}", option_env!("CARGO_PKG_VERSION").unwrap_or("0.0.0")))
-				                                       .send()
+				                                       .send_metered()
 				                                       .await?
 				                                       .error_for_status()?
 				                                       .bytes()
@@ -0,0 +1,543 @@
+use std::io::BufReader;
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use ical::IcalParser;
+use ical::parser::ical::component::IcalEvent;
+use itertools::Itertools;
+use anyhow::Result;
+use thiserror::Error;
+
+use super::Provider;
+use crate::utils::{Json, Map, hash, IteratorEx};
+use crate::config::ConfigFeedEntry;
+use crate::metrics::RequestBuilderEx;
+use crate::feeds::{Feed, Entry};
+
+const MAX_CON_REQUESTS: usize = 10;
+const DEFAULT_UPCOMING_MINUTES: i64 = 60;
+// Upper bound of occurrences checked per recurring event, protects against runaway rules
+const MAX_OCCURRENCES: usize = 100_000;
+
+pub struct IcsProvider;
+
+impl IcsProvider {
+	pub fn new(_config: Json) -> Result<Self> {
+		Ok(IcsProvider)
+	}
+}
+
+#[derive(Deserialize)]
+pub(super) struct ProviderData {
+	// URL of the calendar or path to local .ics file
+	source: String,
+	#[serde(rename="upcomingMinutes")]
+	upcoming_minutes: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct Extra<'a> {
+	start: i64,
+	end: i64,
+	#[serde(rename="allDay")]
+	all_day: bool,
+	location: Option<&'a str>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Zone {
+	Utc,
+	Tz(Tz),
+	Floating,
+}
+
+impl Zone {
+	fn resolve(&self, time: NaiveDateTime) -> Option<DateTime<Utc>> {
+		match self {
+			Zone::Utc => Some(Utc.from_utc_datetime(&time)),
+			Zone::Tz(tz) => tz.from_local_datetime(&time).earliest().map(|time| time.with_timezone(&Utc)),
+			Zone::Floating => Local.from_local_datetime(&time).earliest().map(|time| time.with_timezone(&Utc)),
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Time {
+	local: NaiveDateTime,
+	zone: Zone,
+	all_day: bool,
+}
+
+impl Time {
+	fn parse(value: &str, params: &[(String, Vec<String>)]) -> Option<Time> {
+		let tzid = params.iter()
+		                 .find(|(key, _)| key == "TZID")
+		                 .and_then(|(_, values)| values.first())
+		                 .and_then(|tzid| tzid.parse::<Tz>().ok());
+		
+		if let Ok(date) = NaiveDate::parse_from_str(value, "%Y%m%d") {
+			return Some(Time { local: date.and_hms_opt(0, 0, 0)?, zone: tzid.map_or(Zone::Floating, Zone::Tz), all_day: true });
+		}
+		
+		if let Some(value) = value.strip_suffix('Z') {
+			let local = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+			return Some(Time { local, zone: Zone::Utc, all_day: false });
+		}
+		
+		let local = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+		Some(Time { local, zone: tzid.map_or(Zone::Floating, Zone::Tz), all_day: false })
+	}
+	
+	fn utc(&self) -> Option<DateTime<Utc>> {
+		self.zone.resolve(self.local)
+	}
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Frequency {
+	Daily,
+	Weekly,
+	Monthly,
+	Yearly,
+}
+
+#[derive(Debug)]
+struct RRule {
+	freq: Frequency,
+	interval: u32,
+	count: Option<usize>,
+	until: Option<DateTime<Utc>>,
+	by_day: Vec<Weekday>,
+}
+
+impl RRule {
+	fn parse(value: &str) -> Option<RRule> {
+		let parts: Map<&str> = value.split(';')
+		                            .filter_map(|part| part.split_once('='))
+		                            .map(|(key, value)| (key.to_uppercase(), value))
+		                            .collect();
+		
+		let freq = match *parts.get("FREQ")? {
+			"DAILY" => Frequency::Daily,
+			"WEEKLY" => Frequency::Weekly,
+			"MONTHLY" => Frequency::Monthly,
+			"YEARLY" => Frequency::Yearly,
+			_ => return None,
+		};
+		
+		let by_day = parts.get("BYDAY")
+		                  .map(|days| days.split(',')
+		                                  .filter_map(|day| match day.trim_start_matches(|c: char| c == '+' || c == '-' || c.is_ascii_digit()) {
+			                                  "MO" => Some(Weekday::Mon),
+			                                  "TU" => Some(Weekday::Tue),
+			                                  "WE" => Some(Weekday::Wed),
+			                                  "TH" => Some(Weekday::Thu),
+			                                  "FR" => Some(Weekday::Fri),
+			                                  "SA" => Some(Weekday::Sat),
+			                                  "SU" => Some(Weekday::Sun),
+			                                  _ => None,
+		                                  })
+		                                  .collect())
+		                  .unwrap_or_default();
+		
+		Some(RRule {
+			freq,
+			interval: parts.get("INTERVAL").and_then(|i| i.parse().ok()).filter(|i| *i > 0).unwrap_or(1),
+			count: parts.get("COUNT").and_then(|c| c.parse().ok()),
+			until: parts.get("UNTIL").and_then(|until| Time::parse(until, &[])).and_then(|until| until.utc()),
+			by_day,
+		})
+	}
+	
+	// Local start times of consecutive occurrences, starting with dtstart itself
+	fn occurrences(&self, start: NaiveDateTime) -> impl Iterator<Item = NaiveDateTime> + '_ {
+		let by_day = if self.freq == Frequency::Weekly && !self.by_day.is_empty() {
+			self.by_day.iter().map(|day| day.num_days_from_monday()).sorted().collect()
+		} else {
+			vec![]
+		};
+		let week_start = start.checked_sub_signed(Duration::days(start.weekday().num_days_from_monday() as i64));
+		
+		// Stops once occurrences get out of range of dates
+		(0..).map_while(move |period: i64| {
+			let step = period.checked_mul(self.interval as i64)?;
+			
+			let times = match self.freq {
+				Frequency::Daily => vec![start.checked_add_signed(Duration::try_days(step)?)?],
+				Frequency::Weekly if !by_day.is_empty() => {
+					let week = week_start?.checked_add_signed(Duration::try_weeks(step)?)?;
+					by_day.iter()
+					      .filter_map(|day| week.checked_add_signed(Duration::days(*day as i64)))
+					      .filter(|time| *time >= start)
+					      .collect()
+				},
+				Frequency::Weekly => vec![start.checked_add_signed(Duration::try_weeks(step)?)?],
+				Frequency::Monthly => {
+					let months = (start.month0() as i64).checked_add(step)?;
+					let year = start.year().checked_add(i32::try_from(months / 12).ok()?)?;
+					start.with_day(1)?
+					     .with_year(year)?
+					     .with_month0((months % 12) as u32)?
+					     .with_day(start.day())
+					     .into_iter()
+					     .collect()
+				},
+				Frequency::Yearly => start.with_year(start.year().checked_add(i32::try_from(step).ok()?)?).into_iter().collect(),
+			};
+			
+			Some(times)
+		})
+		.flatten()
+		.take(self.count.unwrap_or(MAX_OCCURRENCES).min(MAX_OCCURRENCES))
+	}
+}
+
+struct Event {
+	uid: String,
+	summary: String,
+	description: Option<String>,
+	location: Option<String>,
+	link: Option<String>,
+	start: Time,
+	duration: Duration,
+	rrule: Option<RRule>,
+	exdates: Vec<DateTime<Utc>>,
+	recurrence_id: Option<DateTime<Utc>>,
+}
+
+// Events which could be parsed, and errors of the ones which couldn't
+struct Calendar {
+	events: Vec<Event>,
+	errors: Vec<anyhow::Error>,
+}
+
+struct Occurrence<'a> {
+	event: &'a Event,
+	start: DateTime<Utc>,
+	end: DateTime<Utc>,
+}
+
+fn unescape(text: &str) -> String {
+	text.replace("\\n", "\n")
+	    .replace("\\N", "\n")
+	    .replace("\\,", ",")
+	    .replace("\\;", ";")
+	    .replace("\\\\", "\\")
+}
+
+fn parse_duration(value: &str) -> Option<Duration> {
+	let (negative, value) = match value.strip_prefix('-') {
+		Some(value) => (true, value),
+		None => (false, value.trim_start_matches('+')),
+	};
+	let value = value.strip_prefix('P')?;
+	
+	let mut duration = Duration::zero();
+	let mut number = String::new();
+	for c in value.chars() {
+		match c {
+			'0'..='9' => number.push(c),
+			'T' => {},
+			unit => {
+				let n: i64 = number.parse().ok()?;
+				number.clear();
+				let part = match unit {
+					'W' => Duration::try_weeks(n),
+					'D' => Duration::try_days(n),
+					'H' => Duration::try_hours(n),
+					'M' => Duration::try_minutes(n),
+					'S' => Duration::try_seconds(n),
+					_ => None,
+				}?;
+				duration = duration.checked_add(&part)?;
+			},
+		}
+	}
+	
+	Some(if negative { -duration } else { duration })
+}
+
+// None for cancelled events and ones without start
+fn parse_event(event: &IcalEvent) -> Result<Option<Event>> {
+	let prop = |name: &str| event.properties.iter().find(|prop| prop.name == name);
+	let value = |name: &str| prop(name).and_then(|prop| prop.value.as_deref());
+	let time = |name: &str| prop(name).and_then(|prop| Time::parse(prop.value.as_deref()?, prop.params.as_deref().unwrap_or_default()));
+	
+	if value("STATUS") == Some("CANCELLED") { return Ok(None) }
+	
+	let Some(start) = time("DTSTART") else { return Ok(None) };
+	let duration = match (time("DTEND"), value("DURATION")) {
+		(Some(end), _) => end.local - start.local,
+		(None, Some(duration)) => parse_duration(duration).ok_or_else(|| InvalidDurationError(duration.to_string()))?,
+		(None, None) if start.all_day => Duration::days(1),
+		(None, None) => Duration::zero(),
+	};
+	
+	if start.local.checked_add_signed(duration).is_none() {
+		return Err(InvalidDurationError(value("DURATION").unwrap_or_default().to_string()).into());
+	}
+	
+	let exdates = event.properties
+	                   .iter()
+	                   .filter(|prop| prop.name == "EXDATE")
+	                   .flat_map(|prop| prop.value
+	                                        .iter()
+	                                        .flat_map(|value| value.split(','))
+	                                        .filter_map(move |value| Time::parse(value, prop.params.as_deref().unwrap_or_default()))
+	                                        .filter_map(|time| time.utc()))
+	                   .collect();
+	
+	Ok(Some(Event {
+		uid: value("UID").map(str::to_string).unwrap_or_else(|| hash(&(value("SUMMARY"), start.local))),
+		summary: value("SUMMARY").map(unescape).unwrap_or_else(|| "<No Title>".to_string()),
+		description: value("DESCRIPTION").map(unescape),
+		location: value("LOCATION").map(unescape),
+		link: value("URL").map(str::to_string),
+		start,
+		duration,
+		rrule: value("RRULE").and_then(RRule::parse),
+		exdates,
+		recurrence_id: time("RECURRENCE-ID").and_then(|time| time.utc()),
+	}))
+}
+
+fn expand(events: &[Event], from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<Occurrence<'_>> {
+	// Modified instances of recurring events, (UID, RECURRENCE-ID)
+	let overrides: Vec<(&str, DateTime<Utc>)> = events.iter()
+	                                                  .filter_map(|event| Some((&*event.uid, event.recurrence_id?)))
+	                                                  .collect();
+	
+	let mut occurrences = vec![];
+	
+	for event in events {
+		let starts = match &event.rrule {
+			Some(rrule) if event.recurrence_id.is_none() => rrule.occurrences(event.start.local).into_box(),
+			_ => Some(event.start.local).into_iter().into_box(),
+		};
+		
+		for local in starts {
+			let Some(start) = event.start.zone.resolve(local) else { continue };
+			let Some(end) = start.checked_add_signed(event.duration) else { break };
+			
+			if start > to { break }
+			if event.rrule.as_ref().and_then(|rrule| rrule.until).is_some_and(|until| start > until) { break }
+			if end <= from && start < from { continue }
+			if event.exdates.contains(&start) { continue }
+			if event.recurrence_id.is_none() && overrides.contains(&(&event.uid, start)) { continue }
+			
+			occurrences.push(Occurrence { event, start, end });
+		}
+	}
+	
+	occurrences
+}
+
+async fn load(client: &reqwest::Client, source: &str) -> Result<Calendar> {
+	let content = if source.starts_with("http://") || source.starts_with("https://") {
+		client.get(source)
+		      .send_metered()
+		      .await?
+		      .error_for_status()?
+		      .bytes()
+		      .await?
+		      .to_vec()
+	} else {
+		tokio::fs::read(source.strip_prefix("file://").unwrap_or(source)).await?
+	};
+	
+	parse_calendar(&content)
+}
+
+fn parse_calendar(content: &[u8]) -> Result<Calendar> {
+	let mut events = vec![];
+	let mut errors = vec![];
+	
+	for calendar in IcalParser::new(BufReader::new(content)) {
+		for event in calendar?.events.iter() {
+			match parse_event(event) {
+				Ok(Some(event)) => events.push(event),
+				Ok(None) => {},
+				Err(err) => errors.push(err),
+			}
+		}
+	}
+	
+	if events.is_empty() && errors.is_empty() && !content.windows(15).any(|w| w == b"BEGIN:VCALENDAR") {
+		return Err(NotACalendarError.into());
+	}
+	
+	Ok(Calendar { events, errors })
+}
+
+#[async_trait(?Send)]
+impl Provider for IcsProvider {
+	async fn fetch(&mut self, config: Map<&ConfigFeedEntry>, client: reqwest::Client) -> Map<Feed> {
+		let client_ref = &client;
+		let calendars = config.values()
+		                      .flat_map(|entry| serde_json::from_value::<ProviderData>(entry.provider_data.clone()))
+		                      .map(|data| data.source)
+		                      .unique()
+		                      .into_stream()
+		                      .map(|source| async move {
+			                      let events = load(client_ref, &source).await;
+			                      (source, events)
+		                      })
+		                      .buffer_unordered(MAX_CON_REQUESTS)
+		                      .collect::<Map<_>>()
+		                      .await;
+		
+		let now = Utc::now();
+		
+		config.into_iter()
+		      .map(|(name, entry)| {
+			      let data: ProviderData = match serde_json::from_value(entry.provider_data.clone()) {
+				      Ok(data) => data,
+				      Err(err) => return (name, Feed::from_err("Unable to parse providerData", &err.into())),
+			      };
+			      
+			      let calendar = match calendars.get(&data.source) {
+				      Some(Ok(calendar)) => calendar,
+				      Some(Err(err)) => return (name, Feed::from_err(&format!("Unable to load calendar {}", data.source), err)),
+				      None => return (name, Feed::new()),
+			      };
+			      
+			      let upcoming_minutes = data.upcoming_minutes.unwrap_or(DEFAULT_UPCOMING_MINUTES);
+			      let upcoming = match Duration::try_minutes(upcoming_minutes).and_then(|upcoming| now.checked_add_signed(upcoming)) {
+				      Some(upcoming) => upcoming,
+				      None => return (name, Feed::from_err("Unable to parse providerData", &InvalidUpcomingMinutesError(upcoming_minutes).into())),
+			      };
+			      
+			      let mut feed = Feed::new();
+			      
+			      for err in &calendar.errors {
+				      feed.add_err(&format!("Skipped event of calendar {}", data.source), err);
+			      }
+			      
+			      for occurrence in expand(&calendar.events, now, upcoming) {
+				      let event = occurrence.event;
+				      let entry = Entry::new(&event.summary, &hash(&(&event.uid, occurrence.start)))
+				                        .set_description(event.location.clone().or(event.description.clone()))
+				                        .set_link(event.link.clone())
+				                        .timestamp(occurrence.start)
+				                        .set_extra(serde_json::to_value(Extra {
+					                        start: occurrence.start.timestamp_millis(),
+					                        end: occurrence.end.timestamp_millis(),
+					                        all_day: event.start.all_day,
+					                        location: event.location.as_deref(),
+				                        }).ok());
+				      
+				      if occurrence.start <= now && occurrence.end > now {
+					      feed.status.push(entry);
+				      } else if occurrence.start > now {
+					      feed.notifications.push(entry);
+				      }
+			      }
+			      
+			      feed.status.sort();
+			      feed.notifications.sort();
+			      
+			      (name, feed)
+		      })
+		      .collect()
+	}
+}
+
+#[derive(Debug, Copy, Clone, Error)]
+#[error("Source is not an iCalendar file")]
+pub struct NotACalendarError;
+
+#[derive(Debug, Clone, Error)]
+#[error("Invalid or out of range event duration {0}")]
+pub struct InvalidDurationError(String);
+
+#[derive(Debug, Copy, Clone, Error)]
+#[error("upcomingMinutes {0} is out of range")]
+pub struct InvalidUpcomingMinutesError(i64);
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn local(value: &str) -> NaiveDateTime {
+		NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap()
+	}
+	
+	fn calendar(events: &str) -> Calendar {
+		parse_calendar(format!("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{}END:VCALENDAR\r\n", events).as_bytes()).unwrap()
+	}
+	
+	#[test]
+	fn parses_durations() {
+		assert_eq!(parse_duration("PT1H30M"), Some(Duration::minutes(90)));
+		assert_eq!(parse_duration("P1W2D"), Some(Duration::days(9)));
+		assert_eq!(parse_duration("-PT15M"), Some(Duration::minutes(-15)));
+		assert_eq!(parse_duration("P1DT12H"), Some(Duration::hours(36)));
+		assert_eq!(parse_duration("1H"), None);
+		assert_eq!(parse_duration("PT1X"), None);
+	}
+	
+	#[test]
+	fn out_of_range_durations() {
+		assert_eq!(parse_duration("P99999999999W"), None);
+		assert_eq!(parse_duration("PT99999999999999999999S"), None);
+		assert_eq!(parse_duration("P10000000000000D10000000000000D"), None);
+	}
+	
+	#[test]
+	fn reports_invalid_events() {
+		let calendar = calendar("BEGIN:VEVENT\r\nUID:a\r\nDTSTART:20240101T100000Z\r\nDURATION:P99999999999W\r\nEND:VEVENT\r\n\
+		                         BEGIN:VEVENT\r\nUID:b\r\nDTSTART:20240101T100000Z\r\nDURATION:PT1H\r\nEND:VEVENT\r\n");
+		
+		assert_eq!(calendar.events.len(), 1);
+		assert_eq!(calendar.events[0].uid, "b");
+		assert_eq!(calendar.errors.len(), 1);
+	}
+	
+	#[test]
+	fn weekly_by_day() {
+		let rrule = RRule::parse("FREQ=WEEKLY;BYDAY=MO,WE;COUNT=4").unwrap();
+		let occurrences = rrule.occurrences(local("2024-01-03 10:00")).collect::<Vec<_>>();
+		
+		assert_eq!(occurrences, vec![local("2024-01-03 10:00"), local("2024-01-08 10:00"), local("2024-01-10 10:00"), local("2024-01-15 10:00")]);
+	}
+	
+	#[test]
+	fn monthly_skips_missing_days() {
+		let rrule = RRule::parse("FREQ=MONTHLY;COUNT=3").unwrap();
+		let occurrences = rrule.occurrences(local("2024-01-31 08:00")).collect::<Vec<_>>();
+		
+		assert_eq!(occurrences, vec![local("2024-01-31 08:00"), local("2024-03-31 08:00"), local("2024-05-31 08:00")]);
+	}
+	
+	#[test]
+	fn huge_intervals_stop() {
+		for freq in &["DAILY", "WEEKLY", "WEEKLY;BYDAY=MO", "MONTHLY", "YEARLY"] {
+			let rrule = RRule::parse(&format!("FREQ={};INTERVAL=4294967295", freq)).unwrap();
+			assert!(rrule.occurrences(local("2024-01-01 00:00")).count() < MAX_OCCURRENCES);
+		}
+	}
+	
+	#[test]
+	fn expands_with_exdates_and_overrides() {
+		let calendar = calendar("BEGIN:VEVENT\r\nUID:a\r\nSUMMARY:Daily\r\nDTSTART:20240101T100000Z\r\nDTEND:20240101T110000Z\r\n\
+		                         RRULE:FREQ=DAILY;UNTIL=20240105T100000Z\r\nEXDATE:20240102T100000Z\r\nEND:VEVENT\r\n\
+		                         BEGIN:VEVENT\r\nUID:a\r\nSUMMARY:Moved\r\nRECURRENCE-ID:20240103T100000Z\r\nDTSTART:20240103T150000Z\r\nDTEND:20240103T160000Z\r\nEND:VEVENT\r\n");
+		
+		let from = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+		let to = Utc.with_ymd_and_hms(2024, 1, 10, 0, 0, 0).unwrap();
+		let starts = expand(&calendar.events, from, to).iter()
+		                                               .map(|occurrence| (occurrence.event.summary.as_str(), occurrence.start.format("%d %H").to_string()))
+		                                               .sorted()
+		                                               .collect::<Vec<_>>();
+		
+		assert_eq!(starts, vec![
+			("Daily", "01 10".to_string()),
+			("Daily", "04 10".to_string()),
+			("Daily", "05 10".to_string()),
+			("Moved", "03 15".to_string()),
+		]);
+	}
+}
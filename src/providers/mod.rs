@@ -1,17 +1,26 @@
 use std::time::{Duration, Instant};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use async_trait::async_trait;
+use futures::FutureExt;
 use futures::future::join_all;
 use anyhow::Result;
 use thiserror::Error;
 use serde::Deserialize;
+use serde::de::DeserializeOwned;
 use tokio::time;
+use chrono::{DateTime, Utc};
+use tracing::{debug, info, warn, error, info_span, Instrument};
 
 mod null;
 mod rss;
 mod youtube;
 mod chan;
 mod vinesauce;
+mod news;
+mod ics;
+mod steam;
+mod releases;
 
 use null::NullProvider;
 use youtube::YouTubeProvider;
@@ -21,7 +30,15 @@ use crate::config::ConfigFeedEntry;
 use crate::feeds::{Feed, Feeds};
 use crate::utils::{Map, Json};
 use crate::state::State;
+use crate::metrics;
 use crate::providers::vinesauce::VinesauceProvider;
+use crate::providers::news::{NewsProvider, Site};
+use crate::providers::ics::IcsProvider;
+use crate::providers::steam::SteamProvider;
+use crate::providers::releases::ReleasesProvider;
+
+// Refreshes requested in quick succession, eg. several feeds changed through the API, share one fetch
+const REFRESH_DELAY: Duration = Duration::from_secs(2);
 
 #[async_trait(?Send)]
 trait Provider: Send {
@@ -30,6 +47,97 @@ trait Provider: Send {
 
 pub struct Providers {
 	providers: Map<Box<dyn Provider>>,
+	statuses: Map<ProviderStatus>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProviderStatus {
+	pub enabled: bool,
+	// Error which prevented provider from loading
+	pub load_error: Option<String>,
+	pub last_fetch_start: Option<DateTime<Utc>>,
+	// When the last fetch ended
+	pub last_fetch: Option<DateTime<Utc>>,
+	pub fetch_duration: Option<Duration>,
+	pub feeds: usize,
+	// Feeds with errors in the last fetch
+	pub failing_feeds: usize,
+	// Fetches in a row with any failing feeds
+	pub consecutive_failures: u32,
+	pub feed_statuses: Map<FeedStatus>,
+}
+
+// Feeds of a provider are fetched together, so they share fetch times
+#[derive(Clone, Debug, Default)]
+pub struct FeedStatus {
+	pub status: usize,
+	pub notifications: usize,
+	pub errors: usize,
+	pub consecutive_failures: u32,
+	// Kept after the feed recovers
+	pub last_error: Option<String>,
+	pub last_error_at: Option<DateTime<Utc>>,
+}
+
+// Fetch loop as a whole, all providers are fetched at once
+#[derive(Clone, Debug, Default)]
+pub struct FetchStatus {
+	pub started: Option<DateTime<Utc>>,
+	// None until the first fetch is done
+	pub finished: Option<DateTime<Utc>>,
+	pub next: Option<DateTime<Utc>>,
+}
+
+impl ProviderStatus {
+	fn new(enabled: bool, load_error: Option<String>) -> Self {
+		ProviderStatus {
+			enabled,
+			load_error,
+			last_fetch_start: None,
+			last_fetch: None,
+			fetch_duration: None,
+			feeds: 0,
+			failing_feeds: 0,
+			consecutive_failures: 0,
+			feed_statuses: Map::new(),
+		}
+	}
+	
+	pub fn is_healthy(&self) -> bool {
+		!self.enabled || (self.load_error.is_none() && self.failing_feeds == 0)
+	}
+	
+	fn record_fetch(&mut self, feeds: &Map<Feed>, started: DateTime<Utc>, duration: Duration) {
+		let mut previous = std::mem::take(&mut self.feed_statuses);
+		
+		self.last_fetch_start = Some(started);
+		self.last_fetch = Some(started + chrono::Duration::from_std(duration).unwrap_or_default());
+		self.fetch_duration = Some(duration);
+		self.feeds = feeds.len();
+		self.failing_feeds = feeds.values().filter(|feed| !feed.errors.is_empty()).count();
+		self.consecutive_failures = if self.failing_feeds > 0 { self.consecutive_failures + 1 } else { 0 };
+		self.feed_statuses = feeds.iter()
+		                          .map(|(name, feed)| (name.clone(), previous.remove(name).unwrap_or_default().record_fetch(feed)))
+		                          .collect();
+	}
+}
+
+impl FeedStatus {
+	fn record_fetch(self, feed: &Feed) -> Self {
+		let error = feed.errors.first();
+		
+		FeedStatus {
+			status: feed.status.len(),
+			notifications: feed.notifications.len(),
+			errors: feed.errors.len(),
+			consecutive_failures: if error.is_some() { self.consecutive_failures + 1 } else { 0 },
+			last_error: error.map(|error| match &error.description {
+				Some(description) => format!("{}: {}", error.title, description),
+				None => error.title.clone(),
+			}).or(self.last_error),
+			last_error_at: error.map(|error| error.timestamp.unwrap_or_else(Utc::now)).or(self.last_error_at),
+		}
+	}
 }
 
 fn boxed<P: Provider + 'static>(result: Result<P>) -> Result<Box<dyn Provider>> {
@@ -44,35 +152,78 @@ struct AnyProviderConfig {
 	enabled: bool
 }
 
-fn init_provider(name: String, config: Json) -> Box<dyn Provider> {
-	match serde_json::from_value(config.clone()) {
-		Ok(AnyProviderConfig{ enabled }) if !enabled => return Box::new(NullProvider::new(ProviderDisabledError.into())),
-		Err(err) => return Box::new(NullProvider::new(err.into())),
-		_ => {},
-	}
+fn create_provider(name: &str, config: Json, data_dir: &Path) -> Result<Box<dyn Provider>> {
+	let AnyProviderConfig{ enabled } = serde_json::from_value(config.clone())?;
+	if !enabled { return Err(ProviderDisabledError.into()) }
 	
 	// Add new providers here
-	let provider = match &*name {
-		"rss" => boxed(RssProvider::new(config)),
+	match name {
+		"rss" => boxed(RssProvider::new(config, false)),
+		"podcast" => boxed(RssProvider::new(config, true)),
 		"youtube" => boxed(YouTubeProvider::new(config)),
 		"chan" => boxed(ChanProvider::new(config)),
 		"vinesauce" => boxed(VinesauceProvider::new(config)),
+		"hackernews" => boxed(NewsProvider::new(config, Site::HackerNews)),
+		"lobsters" => boxed(NewsProvider::new(config, Site::Lobsters)),
+		"ics" => boxed(IcsProvider::new(config)),
+		"steam" => boxed(SteamProvider::new(config)),
+		"releases" => boxed(ReleasesProvider::new(config, data_dir)),
 		_ => Err(ProviderNotFoundError.into()),
-	};
-	
-	provider.unwrap_or_else(|err| {
-		eprintln!("Unable to load {} provider: {}", name, err.to_string());
-		Box::new(NullProvider::new(err.into()))
-	})
+	}
+}
+
+fn init_provider(name: &str, config: Json, data_dir: &Path) -> (Box<dyn Provider>, ProviderStatus) {
+	match create_provider(name, config, data_dir) {
+		Ok(provider) => (provider, ProviderStatus::new(true, None)),
+		Err(err) if err.is::<ProviderDisabledError>() => (Box::new(NullProvider::new(err)), ProviderStatus::new(false, None)),
+		Err(err) => {
+			error!(provider = name, error = %err, "Unable to load provider");
+			let status = ProviderStatus::new(true, Some(err.to_string()));
+			(Box::new(NullProvider::new(err)), status)
+		},
+	}
+}
+
+// Checks if provider can be loaded with given config, without fetching anything. Disabled providers are valid
+pub fn validate_provider(name: &str, config: Json, data_dir: &Path) -> Result<()> {
+	match create_provider(name, config, data_dir) {
+		Err(err) if err.is::<ProviderDisabledError>() => Ok(()),
+		result => result.map(|_| ()),
+	}
+}
+
+fn parse<T: DeserializeOwned>(provider_data: &Json) -> Result<()> {
+	serde_json::from_value::<T>(provider_data.clone())?;
+	Ok(())
+}
+
+// Checks if feed's providerData can be parsed by the provider, same as it would be during fetch
+pub fn validate_provider_data(provider: &str, provider_data: &Json) -> Result<()> {
+	// Add new providers here
+	match provider {
+		"rss" | "podcast" | "youtube" => parse::<String>(provider_data),
+		"chan" => parse::<chan::ProviderData>(provider_data),
+		"vinesauce" => parse::<Option<vinesauce::ProviderData>>(provider_data),
+		"hackernews" | "lobsters" => parse::<news::ProviderData>(provider_data),
+		"ics" => parse::<ics::ProviderData>(provider_data),
+		"steam" => parse::<steam::ProviderData>(provider_data),
+		"releases" => parse::<releases::ProviderData>(provider_data),
+		_ => Err(ProviderNotFoundError.into()),
+	}
 }
 
 impl Providers {
-	pub fn new(configs: Map<Json>) -> Self {
-		let providers = configs.into_iter()
-		                       .map(|(name, config)| (name.clone(), init_provider(name, config)))
-		                       .collect();
+	pub fn new(configs: Map<Json>, data_dir: PathBuf) -> Self {
+		let mut providers = Map::new();
+		let mut statuses = Map::new();
+		
+		for (name, config) in configs {
+			let (provider, status) = init_provider(&name, config, &data_dir);
+			providers.insert(name.clone(), provider);
+			statuses.insert(name, status);
+		}
 		
-		Providers { providers }
+		Providers { providers, statuses }
 	}
 	
 	pub async fn fetch_feeds(&mut self, feeds_configs: &Map<ConfigFeedEntry>, client: reqwest::Client) -> Feeds {
@@ -86,7 +237,10 @@ impl Providers {
 			                                                                            .map(|(name, entry)| (name.clone(), entry))
 			                                                                            .collect();
 			                          
+			                          let started_at = Utc::now();
+			                          let started = Instant::now();
 			                          let mut feeds = provider.fetch(configs, client.clone()).await;
+			                          let duration = started.elapsed();
 			                          
 			                          for (name, feed) in feeds.iter_mut() {
 				                          if let Some(config) = feeds_configs.get(name) {
@@ -95,31 +249,83 @@ impl Providers {
 						                          entry.color = entry.color.take().or(config.color.clone());
 					                          }
 				                          }
+				                          
+				                          if let Some(error) = feed.errors.first() {
+					                          warn!(feed = %name, errors = feed.errors.len(), error = %error.title, "Feed fetched with errors");
+				                          }
 			                          }
 			                          
-			                          feeds
-		                          });
+			                          debug!(feeds = feeds.len(), duration_ms = duration.as_millis() as u64, "Provider fetched");
+			                          
+			                          (name.clone(), feeds, started_at, duration)
+		                          }.instrument(info_span!("provider", provider = %name)));
+		
+		let mut result = Feeds::new();
 		
-		join_all(feeds).await
-		               .into_iter()
-		               .fold(Feeds::new(), |mut acc, mut feeds| { acc.append(&mut feeds); acc })
+		for (name, mut feeds, started, duration) in join_all(feeds).await {
+			if let Some(status) = self.statuses.get_mut(&name) {
+				status.record_fetch(&feeds, started, duration);
+			}
+			
+			metrics::record_fetch(&name, duration);
+			
+			result.append(&mut feeds);
+		}
+		
+		result
 	}
 	
+	
 	pub async fn fetch_loop(&mut self, state: State, fetch_interval: Duration) -> Result<()> {
 		let mut interval = time::interval(fetch_interval);
 		let client = reqwest::Client::new();
+		state.providers.store(Arc::new(self.statuses.clone()));
 		
-		loop {
-			interval.tick().await;
-			
-			println!("Fetching feeds...");
-			let now = Instant::now();
-			
-			let feeds = self.fetch_feeds(&state.feed_entries.load(), client.clone()).await;
-			state.feeds.store(Arc::new(feeds));
+		for cycle in 1.. {
+			tokio::select! {
+				_ = interval.tick() => {},
+				_ = state.refresh.notified() => {
+					time::sleep(REFRESH_DELAY).await;
+					let _ = state.refresh.notified().now_or_never();
+					interval.reset();
+				},
+			}
 			
-			println!("Fetch done. ({}s)", (now.elapsed().as_secs_f32() * 100.0).round() / 100.0);
+			self.fetch_cycle(&state, fetch_interval, client.clone())
+			    .instrument(info_span!("fetch", cycle))
+			    .await;
 		}
+		
+		Ok(())
+	}
+	
+	async fn fetch_cycle(&mut self, state: &State, fetch_interval: Duration, client: reqwest::Client) {
+		info!("Fetching feeds...");
+		let now = Instant::now();
+		let started = Utc::now();
+		state.fetch.rcu(|fetch| FetchStatus { started: Some(started), ..FetchStatus::clone(fetch) });
+		
+		let previous = state.feeds.load_full();
+		let mut feeds = self.fetch_feeds(&state.feed_entries.load(), client).await;
+		feeds.track(&previous);
+		metrics::record_feeds(&feeds);
+		let new_entries = feeds.new_entries(&previous).cloned().collect::<Vec<_>>();
+		let new_count = new_entries.len();
+		state.feeds.store(Arc::new(feeds));
+		state.providers.store(Arc::new(self.statuses.clone()));
+		
+		// Interval starts over on refresh too, so the next fetch is always one interval after this one started
+		let finished = Utc::now();
+		state.fetch.store(Arc::new(FetchStatus {
+			started: Some(started),
+			finished: Some(finished),
+			next: Some((started + chrono::Duration::from_std(fetch_interval).unwrap_or_default()).max(finished)),
+		}));
+		
+		// Sent even when empty, so interfaces can follow every snapshot. Fails only when no interface is listening
+		let _ = state.new_entries.send(Arc::new(new_entries));
+		
+		info!(duration_ms = now.elapsed().as_millis() as u64, new_entries = new_count, "Fetch done");
 	}
 }
 
@@ -0,0 +1,294 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use anyhow::Result;
+
+use super::Provider;
+use crate::utils::{Json, Map, IteratorEx, build_regex};
+use crate::config::ConfigFeedEntry;
+use crate::metrics::RequestBuilderEx;
+use crate::feeds::{Feed, Entry};
+
+const MAX_CON_REQUESTS: usize = 10;
+const DEFAULT_LIMIT: usize = 30;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Site {
+	HackerNews,
+	Lobsters,
+}
+
+pub struct NewsProvider {
+	site: Site,
+}
+
+impl NewsProvider {
+	pub fn new(_config: Json, site: Site) -> Result<Self> {
+		Ok(NewsProvider { site })
+	}
+}
+
+#[derive(Deserialize, Clone, Hash, Eq, PartialEq)]
+pub(super) struct ProviderData {
+	// HN: "top", "new", "best", "ask", "show". Lobsters: "hottest", "newest", "active"
+	list: Option<String>,
+	// HN: Algolia full text search. Lobsters: regex matched against titles and tags
+	query: Option<String>,
+	// Lobsters only
+	tag: Option<String>,
+	#[serde(rename="minScore", default)]
+	min_score: i64,
+	#[serde(rename="minComments", default)]
+	min_comments: i64,
+	limit: Option<usize>,
+}
+
+impl ProviderData {
+	fn limit(&self) -> usize {
+		self.limit.unwrap_or(DEFAULT_LIMIT)
+	}
+}
+
+struct Story {
+	id: String,
+	title: String,
+	url: Option<String>,
+	comments_url: String,
+	author: Option<String>,
+	score: i64,
+	comments: i64,
+	tags: Vec<String>,
+	timestamp: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+struct Extra<'a> {
+	site: &'static str,
+	score: i64,
+	comments: i64,
+	author: &'a Option<String>,
+	#[serde(rename="commentsLink")]
+	comments_link: &'a str,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	tags: &'a Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct HnItem {
+	id: u64,
+	title: Option<String>,
+	url: Option<String>,
+	by: Option<String>,
+	score: Option<i64>,
+	descendants: Option<i64>,
+	time: i64,
+	dead: Option<bool>,
+	deleted: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct HnSearch {
+	hits: Vec<HnHit>,
+}
+
+#[derive(Deserialize)]
+struct HnHit {
+	#[serde(rename="objectID")]
+	object_id: String,
+	title: Option<String>,
+	url: Option<String>,
+	author: Option<String>,
+	points: Option<i64>,
+	num_comments: Option<i64>,
+	created_at_i: i64,
+}
+
+#[derive(Deserialize)]
+struct LobstersStory {
+	short_id: String,
+	title: String,
+	url: String,
+	score: i64,
+	comment_count: i64,
+	created_at: String,
+	comments_url: String,
+	submitter_user: Json,
+	tags: Vec<String>,
+}
+
+async fn get<T: DeserializeOwned>(client: &reqwest::Client, url: &str) -> Result<T> {
+	let bytes = client.get(url)
+	                  .send_metered()
+	                  .await?
+	                  .error_for_status()?
+	                  .bytes()
+	                  .await?;
+	
+	Ok(serde_json::from_slice(&bytes)?)
+}
+
+async fn fetch_hn(client: &reqwest::Client, data: &ProviderData) -> Result<Vec<Story>> {
+	if let Some(query) = &data.query {
+		let url = format!("https://hn.algolia.com/api/v1/search?tags=story&hitsPerPage={}&query={}",
+		                  data.limit(), utf8_percent_encode(query, NON_ALPHANUMERIC));
+		let search: HnSearch = get(client, &url).await?;
+		
+		return Ok(search.hits
+		                .into_iter()
+		                .map(|hit| Story {
+			                comments_url: format!("https://news.ycombinator.com/item?id={}", hit.object_id),
+			                id: hit.object_id,
+			                title: hit.title.unwrap_or_else(|| "<No Title>".to_string()),
+			                url: hit.url,
+			                author: hit.author,
+			                score: hit.points.unwrap_or(0),
+			                comments: hit.num_comments.unwrap_or(0),
+			                tags: vec![],
+			                timestamp: DateTime::from_timestamp(hit.created_at_i, 0),
+		                })
+		                .collect());
+	}
+	
+	let list = data.list.as_deref().unwrap_or("top");
+	let ids: Vec<u64> = get(client, &format!("https://hacker-news.firebaseio.com/v0/{}stories.json", utf8_percent_encode(list, NON_ALPHANUMERIC))).await?;
+	
+	let items = ids.into_iter()
+	               .take(data.limit())
+	               .into_stream()
+	               .map(|id| async move {
+		               get::<Option<HnItem>>(client, &format!("https://hacker-news.firebaseio.com/v0/item/{}.json", id)).await
+	               })
+	               .buffered(MAX_CON_REQUESTS)
+	               .collect::<Vec<_>>()
+	               .await;
+	
+	let mut stories = vec![];
+	for item in items {
+		let item = match item? {
+			Some(item) if !item.dead.unwrap_or(false) && !item.deleted.unwrap_or(false) => item,
+			_ => continue,
+		};
+		
+		stories.push(Story {
+			id: item.id.to_string(),
+			title: item.title.unwrap_or_else(|| "<No Title>".to_string()),
+			url: item.url,
+			comments_url: format!("https://news.ycombinator.com/item?id={}", item.id),
+			author: item.by,
+			score: item.score.unwrap_or(0),
+			comments: item.descendants.unwrap_or(0),
+			tags: vec![],
+			timestamp: DateTime::from_timestamp(item.time, 0),
+		});
+	}
+	
+	Ok(stories)
+}
+
+async fn fetch_lobsters(client: &reqwest::Client, data: &ProviderData) -> Result<Vec<Story>> {
+	let url = match &data.tag {
+		Some(tag) => format!("https://lobste.rs/t/{}.json", utf8_percent_encode(tag, NON_ALPHANUMERIC)),
+		None => format!("https://lobste.rs/{}.json", utf8_percent_encode(data.list.as_deref().unwrap_or("hottest"), NON_ALPHANUMERIC)),
+	};
+	
+	let query = data.query.as_deref().map(build_regex).transpose()?;
+	
+	let stories: Vec<LobstersStory> = get(client, &url).await?;
+	
+	Ok(stories.into_iter()
+	          .filter(|story| query.as_ref().is_none_or(|query| query.is_match(&story.title) || story.tags.iter().any(|tag| query.is_match(tag))))
+	          .take(data.limit())
+	          .map(|story| Story {
+		          id: story.short_id,
+		          title: story.title,
+		          url: Some(story.url).filter(|url| !url.is_empty()),
+		          comments_url: story.comments_url,
+		          author: match story.submitter_user {
+			          Json::String(user) => Some(user),
+			          Json::Object(user) => user.get("username").and_then(Json::as_str).map(str::to_string),
+			          _ => None,
+		          },
+		          score: story.score,
+		          comments: story.comment_count,
+		          tags: story.tags,
+		          timestamp: DateTime::parse_from_rfc3339(&story.created_at).ok().map(|ts| ts.with_timezone(&Utc)),
+	          })
+	          .collect())
+}
+
+#[async_trait(?Send)]
+impl Provider for NewsProvider {
+	async fn fetch(&mut self, config: Map<&ConfigFeedEntry>, client: reqwest::Client) -> Map<Feed> {
+		let site = self.site;
+		let client_ref = &client;
+		
+		let requests = config.values()
+		                     .flat_map(|entry| serde_json::from_value::<ProviderData>(entry.provider_data.clone()))
+		                     .unique()
+		                     .collect::<Vec<_>>();
+		
+		let results = requests.iter()
+		                      .into_stream()
+		                      .map(|data| async move {
+			                      let stories = match site {
+				                      Site::HackerNews => fetch_hn(client_ref, data).await,
+				                      Site::Lobsters => fetch_lobsters(client_ref, data).await,
+			                      };
+			                      
+			                      (data, stories)
+		                      })
+		                      .buffer_unordered(MAX_CON_REQUESTS)
+		                      .collect::<Vec<_>>()
+		                      .await;
+		
+		config.into_iter()
+		      .map(|(name, entry)| {
+			      let data: ProviderData = match serde_json::from_value(entry.provider_data.clone()) {
+				      Ok(data) => data,
+				      Err(err) => return (name, Feed::from_err("Unable to parse providerData", &err.into())),
+			      };
+			      
+			      let stories = match results.iter().find(|(key, _)| **key == data) {
+				      Some((_, Ok(stories))) => stories,
+				      Some((_, Err(err))) => return (name, Feed::from_err("Unable to fetch stories", err)),
+				      None => return (name, Feed::new()),
+			      };
+			      
+			      let mut feed = Feed::new();
+			      
+			      // Stories keep their guid as the score changes, so they are updated in place rather than notified again
+			      feed.notifications = stories.iter()
+			                                  .filter(|story| story.score >= data.min_score && story.comments >= data.min_comments)
+			                                  .map(|story| story_entry(site, story))
+			                                  .sorted()
+			                                  .collect();
+			      
+			      (name, feed)
+		      })
+		      .collect()
+	}
+}
+
+fn story_entry(site: Site, story: &Story) -> Entry {
+	let (prefix, site_name) = match site {
+		Site::HackerNews => ("hn", "hackernews"),
+		Site::Lobsters => ("lobsters", "lobsters"),
+	};
+	
+	Entry::new(&story.title, &format!("{}-{}", prefix, story.id))
+	      .description(&format!("{} points, {} comments", story.score, story.comments))
+	      .link(story.url.as_ref().unwrap_or(&story.comments_url))
+	      .set_timestamp(story.timestamp)
+	      .set_extra(serde_json::to_value(Extra {
+		      site: site_name,
+		      score: story.score,
+		      comments: story.comments,
+		      author: &story.author,
+		      comments_link: &story.comments_url,
+		      tags: &story.tags,
+	      }).ok())
+}
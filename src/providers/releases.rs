@@ -0,0 +1,460 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
+use regex::Regex;
+use reqwest::header;
+use semver::Version;
+use anyhow::Result;
+use thiserror::Error;
+
+use super::Provider;
+use crate::utils::{Json, Map, IteratorEx, load_data, save_data};
+use crate::config::ConfigFeedEntry;
+use crate::metrics::RequestBuilderEx;
+use crate::feeds::{Feed, Entry};
+
+const MAX_CON_REQUESTS: usize = 4;
+// Amount of detected releases remembered per package
+const MAX_RELEASES: usize = 20;
+
+static VERSION_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^v?(\d+)(?:\.(\d+))?(?:\.(\d+))?[.\-_]?(.*)$").unwrap());
+
+pub struct ReleasesProvider {
+	path: PathBuf,
+	data: Option<ReleasesData>,
+}
+
+impl ReleasesProvider {
+	pub fn new(_config: Json, data_dir: &Path) -> Result<Self> {
+		Ok(ReleasesProvider {
+			path: data_dir.join("releases.json"),
+			data: None,
+		})
+	}
+}
+
+// Persisted in the data directory, so restarts don't notify about old releases
+type ReleasesData = Map<PackageData>;
+
+#[derive(Serialize, Deserialize, Default)]
+struct PackageData {
+	seen: BTreeSet<String>,
+	releases: Vec<Release>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Release {
+	version: String,
+	published: Option<DateTime<Utc>>,
+	detected: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(super) enum ProviderData {
+	Packages(Vec<String>),
+	Full {
+		packages: Vec<String>,
+		#[serde(default)]
+		prereleases: bool,
+		#[serde(default)]
+		bump: Bump,
+	},
+}
+
+impl ProviderData {
+	fn packages(&self) -> &Vec<String> {
+		match self {
+			ProviderData::Packages(packages) => packages,
+			ProviderData::Full { packages, .. } => packages,
+		}
+	}
+	
+	fn prereleases(&self) -> bool {
+		match self {
+			ProviderData::Packages(_) => false,
+			ProviderData::Full { prereleases, .. } => *prereleases,
+		}
+	}
+	
+	fn bump(&self) -> Bump {
+		match self {
+			ProviderData::Packages(_) => Bump::default(),
+			ProviderData::Full { bump, .. } => *bump,
+		}
+	}
+}
+
+#[derive(Deserialize, Copy, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum Bump {
+	Major,
+	Minor,
+	#[default]
+	Patch,
+}
+
+#[derive(Serialize)]
+struct Extra<'a> {
+	registry: &'a str,
+	package: &'a str,
+	version: &'a str,
+	prerelease: bool,
+}
+
+struct Package<'a> {
+	registry: &'a str,
+	name: &'a str,
+}
+
+impl<'a> Package<'a> {
+	fn parse(spec: &'a str) -> Result<Self> {
+		let (registry, name) = spec.split_once(':').ok_or(InvalidSpecError)?;
+		
+		match registry {
+			"crates" | "npm" | "pypi" | "docker" => Ok(Package { registry, name }),
+			_ => Err(UnknownRegistryError.into()),
+		}
+	}
+	
+	fn link(&self, version: &str) -> String {
+		match self.registry {
+			"crates" => format!("https://crates.io/crates/{}/{}", self.name, version),
+			"npm" => format!("https://www.npmjs.com/package/{}/v/{}", self.name, version),
+			"pypi" => format!("https://pypi.org/project/{}/{}/", self.name, version),
+			_ => match self.name.strip_prefix("library/") {
+				Some(name) => format!("https://hub.docker.com/_/{}/tags?name={}", name, version),
+				None => format!("https://hub.docker.com/r/{}/tags?name={}", self.name, version),
+			},
+		}
+	}
+}
+
+#[derive(Deserialize)]
+struct CratesResponse {
+	versions: Vec<CratesVersion>,
+}
+
+#[derive(Deserialize)]
+struct CratesVersion {
+	num: String,
+	created_at: DateTime<Utc>,
+	yanked: bool,
+}
+
+#[derive(Deserialize)]
+struct NpmResponse {
+	time: Map<String>,
+}
+
+#[derive(Deserialize)]
+struct PypiResponse {
+	releases: Map<Vec<PypiFile>>,
+}
+
+#[derive(Deserialize)]
+struct PypiFile {
+	upload_time_iso_8601: Option<DateTime<Utc>>,
+	yanked: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct DockerResponse {
+	results: Vec<DockerTag>,
+}
+
+#[derive(Deserialize)]
+struct DockerTag {
+	name: String,
+	last_updated: Option<DateTime<Utc>>,
+}
+
+async fn get<T: DeserializeOwned>(client: &reqwest::Client, url: &str) -> Result<T> {
+	let bytes = client.get(url)
+	                  .header(header::USER_AGENT, format!("rust-notifier/{}", option_env!("CARGO_PKG_VERSION").unwrap_or("0.0.0")))
+	                  .send_metered()
+	                  .await?
+	                  .error_for_status()?
+	                  .bytes()
+	                  .await?;
+	
+	Ok(serde_json::from_slice(&bytes)?)
+}
+
+// Version -> Publish date
+async fn fetch_versions(client: &reqwest::Client, package: &Package<'_>) -> Result<Vec<(String, Option<DateTime<Utc>>)>> {
+	Ok(match package.registry {
+		"crates" => {
+			let response: CratesResponse = get(client, &format!("https://crates.io/api/v1/crates/{}", package.name)).await?;
+			response.versions
+			        .into_iter()
+			        .filter(|version| !version.yanked)
+			        .map(|version| (version.num, Some(version.created_at)))
+			        .collect()
+		},
+		"npm" => {
+			let response: NpmResponse = get(client, &format!("https://registry.npmjs.org/{}", package.name)).await?;
+			response.time
+			        .into_iter()
+			        .filter(|(version, _)| version != "created" && version != "modified")
+			        .map(|(version, time)| (version, DateTime::parse_from_rfc3339(&time).ok().map(|time| time.with_timezone(&Utc))))
+			        .collect()
+		},
+		"pypi" => {
+			let response: PypiResponse = get(client, &format!("https://pypi.org/pypi/{}/json", package.name)).await?;
+			response.releases
+			        .into_iter()
+			        .filter(|(_, files)| !files.is_empty() && !files.iter().all(|file| file.yanked.unwrap_or(false)))
+			        .map(|(version, files)| {
+				        let published = files.iter().filter_map(|file| file.upload_time_iso_8601).min();
+				        (version, published)
+			        })
+			        .collect()
+		},
+		_ => {
+			let name = if package.name.contains('/') { package.name.to_string() } else { format!("library/{}", package.name) };
+			let response: DockerResponse = get(client, &format!("https://hub.docker.com/v2/repositories/{}/tags?page_size=100&ordering=last_updated", name)).await?;
+			response.results
+			        .into_iter()
+			        .map(|tag| (tag.name, tag.last_updated))
+			        .collect()
+		},
+	})
+}
+
+// Registries don't agree on version formats, eg. "v1.2", "2.0.0rc1" or "16-alpine"
+fn parse_version(version: &str) -> Option<Version> {
+	if let Ok(version) = Version::parse(version.trim_start_matches('v')) {
+		return Some(version);
+	}
+	
+	let captures = VERSION_REGEX.captures(version)?;
+	let part = |n: usize| captures.get(n).map_or(Ok(0), |part| part.as_str().parse::<u64>());
+	
+	let mut parsed = Version::new(part(1).ok()?, part(2).ok()?, part(3).ok()?);
+	let rest = captures.get(4).map_or("", |rest| rest.as_str());
+	if !rest.is_empty() {
+		parsed.pre = semver::Prerelease::new(&rest.replace(|c: char| !c.is_ascii_alphanumeric() && c != '.', "-")).ok()?;
+	}
+	
+	Some(parsed)
+}
+
+fn is_bump(version: &Version, latest: Option<&Version>, bump: Bump) -> bool {
+	let Some(latest) = latest else { return true };
+	
+	match bump {
+		Bump::Major => version.major > latest.major,
+		Bump::Minor => (version.major, version.minor) > (latest.major, latest.minor),
+		Bump::Patch => true,
+	}
+}
+
+impl ReleasesProvider {
+	// Records versions not seen before and returns true if anything changed
+	fn update(data: &mut ReleasesData, spec: &str, versions: &[(String, Option<DateTime<Utc>>)]) -> bool {
+		let first_fetch = !data.contains_key(spec);
+		let package = data.entry(spec.to_string()).or_default();
+		let now = Utc::now();
+		
+		let new = versions.iter()
+		                  .filter(|(version, _)| !package.seen.contains(version))
+		                  .collect::<Vec<_>>();
+		
+		if new.is_empty() { return first_fetch }
+		
+		// Everything is new on the first fetch, only remember the versions
+		if !first_fetch {
+			package.releases.extend(new.iter().map(|(version, published)| Release {
+				version: version.clone(),
+				published: *published,
+				detected: now,
+			}));
+			
+			let excess = package.releases.len().saturating_sub(MAX_RELEASES);
+			package.releases.drain(..excess);
+		}
+		
+		package.seen.extend(new.into_iter().map(|(version, _)| version.clone()));
+		
+		true
+	}
+}
+
+#[async_trait(?Send)]
+impl Provider for ReleasesProvider {
+	async fn fetch(&mut self, config: Map<&ConfigFeedEntry>, client: reqwest::Client) -> Map<Feed> {
+		let mut load_error = None;
+		if self.data.is_none() {
+			match load_data(&self.path).await {
+				Ok(data) => self.data = Some(data),
+				Err(err) => load_error = Some(err),
+			}
+		}
+		
+		let client_ref = &client;
+		let fetched = config.values()
+		                    .flat_map(|entry| serde_json::from_value::<ProviderData>(entry.provider_data.clone()))
+		                    .flat_map(|data| data.packages().clone())
+		                    .unique()
+		                    .into_stream()
+		                    .map(|spec| async move {
+			                    let versions = match Package::parse(&spec) {
+				                    Ok(package) => fetch_versions(client_ref, &package).await,
+				                    Err(err) => Err(err),
+			                    };
+			                    
+			                    (spec, versions)
+		                    })
+		                    .buffer_unordered(MAX_CON_REQUESTS)
+		                    .collect::<Map<_>>()
+		                    .await;
+		
+		let mut save_error = None;
+		if let Some(data) = &mut self.data {
+			let mut changed = false;
+			for (spec, versions) in fetched.iter() {
+				if let Ok(versions) = versions {
+					changed |= ReleasesProvider::update(data, spec, versions);
+				}
+			}
+			
+			if changed {
+				save_error = save_data(&self.path, data).await.err();
+			}
+		}
+		
+		config.into_iter()
+		      .map(|(name, entry)| {
+			      let mut feed = Feed::new();
+			      
+			      if let Some(err) = &load_error {
+				      feed.add_err("Unable to load releases data", err);
+			      }
+			      
+			      if let Some(err) = &save_error {
+				      feed.add_err("Unable to save releases data", err);
+			      }
+			      
+			      let provider_data: ProviderData = match serde_json::from_value(entry.provider_data.clone()) {
+				      Ok(provider_data) => provider_data,
+				      Err(err) => { feed.add_err("Unable to parse providerData", &err.into()); return (name, feed) },
+			      };
+			      
+			      for spec in provider_data.packages() {
+				      match fetched.get(spec) {
+					      Some(Err(err)) => { feed.add_err(&format!("Unable to fetch releases of {}", spec), err); continue },
+					      None => continue,
+					      _ => {},
+				      }
+				      
+				      let Ok(package) = Package::parse(spec) else { continue };
+				      let Some(package_data) = self.data.as_ref().and_then(|data| data.get(spec)) else { continue };
+				      
+				      // Latest stable version known before each release, to tell the size of the bump
+				      let mut latest: Option<Version> = package_data.seen
+				                                                    .iter()
+				                                                    .filter(|version| !package_data.releases.iter().any(|release| &release.version == *version))
+				                                                    .filter_map(|version| parse_version(version))
+				                                                    .filter(|version| version.pre.is_empty())
+				                                                    .max();
+				      
+				      for release in package_data.releases.iter() {
+					      let Some(version) = parse_version(&release.version) else { continue };
+					      let prerelease = !version.pre.is_empty();
+					      
+					      if prerelease && !provider_data.prereleases() { continue }
+					      if !is_bump(&version, latest.as_ref(), provider_data.bump()) { continue }
+					      
+					      if !prerelease && latest.as_ref().is_none_or(|latest| &version > latest) {
+						      latest = Some(version.clone());
+					      }
+					      
+					      feed.notifications.push(
+						      Entry::new(&format!("{} {}", package.name, release.version), &format!("{}@{}", spec, release.version))
+						            .description(&format!("New release of {} on {}", package.name, package.registry))
+						            .link(&package.link(&release.version))
+						            .timestamp(release.published.unwrap_or(release.detected))
+						            .set_extra(serde_json::to_value(Extra {
+							            registry: package.registry,
+							            package: package.name,
+							            version: &release.version,
+							            prerelease,
+						            }).ok())
+					      );
+				      }
+			      }
+			      
+			      feed.notifications.sort();
+			      
+			      (name, feed)
+		      })
+		      .collect()
+	}
+}
+
+#[derive(Debug, Copy, Clone, Error)]
+#[error("Package should be specified as registry:package, eg. crates:tokio")]
+pub struct InvalidSpecError;
+
+#[derive(Debug, Copy, Clone, Error)]
+#[error("Unknown registry, supported registries are crates, npm, pypi and docker")]
+pub struct UnknownRegistryError;
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn version(version: &str) -> Version {
+		parse_version(version).unwrap()
+	}
+	
+	#[test]
+	fn parses_versions() {
+		assert_eq!(version("1.2.3"), Version::new(1, 2, 3));
+		assert_eq!(version("v1.2"), Version::new(1, 2, 0));
+		assert_eq!(version("16"), Version::new(16, 0, 0));
+		assert_eq!(version("2.0.0rc1").pre.as_str(), "rc1");
+		assert_eq!(version("16-alpine").pre.as_str(), "alpine");
+		assert!(parse_version("latest").is_none());
+		assert!(parse_version("99999999999999999999.0").is_none());
+	}
+	
+	#[test]
+	fn detects_bumps() {
+		let latest = version("1.2.3");
+		
+		assert!(is_bump(&version("2.0.0"), Some(&latest), Bump::Major));
+		assert!(!is_bump(&version("1.3.0"), Some(&latest), Bump::Major));
+		assert!(is_bump(&version("1.3.0"), Some(&latest), Bump::Minor));
+		assert!(!is_bump(&version("1.2.4"), Some(&latest), Bump::Minor));
+		assert!(is_bump(&version("1.2.4"), Some(&latest), Bump::Patch));
+		assert!(is_bump(&version("0.1.0"), None, Bump::Major));
+	}
+	
+	#[test]
+	fn remembers_versions_on_first_fetch() {
+		let mut data = ReleasesData::new();
+		
+		assert!(ReleasesProvider::update(&mut data, "crates:serde", &[("1.0.0".to_string(), None)]));
+		assert!(data["crates:serde"].releases.is_empty());
+		
+		assert!(!ReleasesProvider::update(&mut data, "crates:serde", &[("1.0.0".to_string(), None)]));
+		
+		assert!(ReleasesProvider::update(&mut data, "crates:serde", &[("1.0.0".to_string(), None), ("1.0.1".to_string(), None)]));
+		assert_eq!(data["crates:serde"].releases.iter().map(|release| release.version.as_str()).collect::<Vec<_>>(), vec!["1.0.1"]);
+	}
+	
+	#[test]
+	fn parses_package_specs() {
+		assert_eq!(Package::parse("docker:library/postgres").unwrap().link("16"), "https://hub.docker.com/_/postgres/tags?name=16");
+		assert!(Package::parse("postgres").is_err());
+		assert!(Package::parse("apt:postgres").is_err());
+	}
+}
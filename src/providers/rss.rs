@@ -1,6 +1,7 @@
 use async_trait::async_trait;
 use futures::{StreamExt, TryFutureExt};
-use rss::Channel;
+use rss::{Channel, Item};
+use serde::Serialize;
 use bytes::Bytes;
 use chrono::DateTime;
 use itertools::Itertools;
@@ -9,21 +10,49 @@ use anyhow::Result;
 use super::Provider;
 use crate::utils::{Json, Map, hash, IteratorEx};
 use crate::config::ConfigFeedEntry;
+use crate::metrics::RequestBuilderEx;
 use crate::feeds::{Feed, Entry};
 
 const MAX_CON_REQUESTS: usize = 10;
 
-pub struct RssProvider;
+pub struct RssProvider {
+	// Exposes enclosures and iTunes extensions of podcast feeds
+	podcast: bool,
+}
 
 impl RssProvider {
-	pub fn new(_config: Json) -> Result<Self> {
-		Ok(RssProvider)
+	pub fn new(_config: Json, podcast: bool) -> Result<Self> {
+		Ok(RssProvider { podcast })
 	}
 }
 
+#[derive(Serialize)]
+struct PodcastExtra<'a> {
+	enclosure: Option<Enclosure<'a>>,
+	// Seconds
+	duration: Option<u64>,
+	episode: Option<u64>,
+	season: Option<u64>,
+	#[serde(rename="episodeType")]
+	episode_type: Option<&'a str>,
+	explicit: Option<&'a str>,
+	author: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct Enclosure<'a> {
+	url: &'a str,
+	#[serde(rename="type")]
+	mime_type: &'a str,
+	length: Option<u64>,
+}
+
 #[async_trait(?Send)]
 impl Provider for RssProvider {
-	async fn fetch(&mut self, config: Map<&ConfigFeedEntry>, _client: reqwest::Client) -> Map<Feed> {
+	async fn fetch(&mut self, config: Map<&ConfigFeedEntry>, client: reqwest::Client) -> Map<Feed> {
+		let podcast = self.podcast;
+		let client = &client;
+		
 		// Url -> Feed
 		let data = config.values()
 		                 .map(|entry| entry.provider_data.as_str())
@@ -31,7 +60,8 @@ impl Provider for RssProvider {
 		                 .unique()
 		                 .into_stream()
 		                 .map(|url| async move {
-			                 let content = reqwest::get(url)
+			                 let content = client.get(url)
+				                 .send_metered()
 				                 .and_then(|res| res.bytes())
 				                 .await;
 			                 
@@ -39,7 +69,7 @@ impl Provider for RssProvider {
 		                 })
 		                 .buffer_unordered(MAX_CON_REQUESTS)
 		                 .map(|(url, content)| {
-			                 let feed = parse_response(content, url);
+			                 let feed = parse_response(content, url, podcast);
 			
 			                 (url.to_string(), feed)
 		                 })
@@ -58,7 +88,7 @@ impl Provider for RssProvider {
 	}
 }
 
-fn parse_response(response: reqwest::Result<Bytes>, url: &str) -> Feed {
+fn parse_response(response: reqwest::Result<Bytes>, url: &str, podcast: bool) -> Feed {
 	let parsed = response.map(|content| Channel::read_from(&*content));
 	
 	match parsed {
@@ -73,10 +103,14 @@ fn parse_response(response: reqwest::Result<Bytes>, url: &str) -> Feed {
 				               .map(|g| g.value().to_string())
 				               .unwrap_or_else(|| hash(&(&title, &timestamp)));
 				
-				let entry = Entry::new(&title, &guid)
-				                  .set_description(item.description().or(item.content().clone()).map(str::to_string))
-				                  .set_link(item.link().map(str::to_string))
-				                  .set_timestamp(timestamp);
+				let mut entry = Entry::new(&title, &guid)
+				                      .set_description(item.description().or(item.content().clone()).map(str::to_string))
+				                      .set_link(item.link().map(str::to_string))
+				                      .set_timestamp(timestamp);
+				
+				if podcast {
+					entry = podcast_entry(entry, &chan, item);
+				}
 				
 				feed.notifications.push(entry);
 			}
@@ -87,3 +121,56 @@ fn parse_response(response: reqwest::Result<Bytes>, url: &str) -> Feed {
 		Err(err) => Feed::from_err(&format!("Unable to fetch {}", url), &err.into()),
 	}
 }
+
+fn podcast_entry(entry: Entry, chan: &Channel, item: &Item) -> Entry {
+	let itunes = item.itunes_ext();
+	let artwork = itunes.and_then(|itunes| itunes.image())
+	                    .or(chan.itunes_ext().and_then(|itunes| itunes.image()))
+	                    .or(chan.image().map(|image| image.url()));
+	
+	let extra = PodcastExtra {
+		enclosure: item.enclosure().map(|enclosure| Enclosure {
+			url: enclosure.url(),
+			mime_type: enclosure.mime_type(),
+			length: enclosure.length().parse().ok().filter(|length| *length > 0),
+		}),
+		duration: itunes.and_then(|itunes| itunes.duration()).and_then(parse_duration),
+		episode: itunes.and_then(|itunes| itunes.episode()).and_then(|episode| episode.trim().parse().ok()),
+		season: itunes.and_then(|itunes| itunes.season()).and_then(|season| season.trim().parse().ok()),
+		episode_type: itunes.and_then(|itunes| itunes.episode_type()),
+		explicit: itunes.and_then(|itunes| itunes.explicit()),
+		author: itunes.and_then(|itunes| itunes.author()).or(item.author()),
+	};
+	
+	entry.set_image_url(artwork.map(str::to_string))
+	     .set_extra(serde_json::to_value(extra).ok())
+}
+
+// itunes:duration is either amount of seconds, "MM:SS" or "HH:MM:SS"
+fn parse_duration(duration: &str) -> Option<u64> {
+	duration.trim()
+	        .split(':')
+	        .try_fold(0u64, |acc, part| acc.checked_mul(60)?.checked_add(part.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn parses_podcast_durations() {
+		assert_eq!(parse_duration("90"), Some(90));
+		assert_eq!(parse_duration(" 12:34 "), Some(754));
+		assert_eq!(parse_duration("1:02:03"), Some(3723));
+		assert_eq!(parse_duration(""), None);
+		assert_eq!(parse_duration("1:xx"), None);
+		assert_eq!(parse_duration("-5"), None);
+	}
+	
+	#[test]
+	fn rejects_overflowing_durations() {
+		assert_eq!(parse_duration(&u64::MAX.to_string()), Some(u64::MAX));
+		assert_eq!(parse_duration(&format!("{}:00", u64::MAX)), None);
+		assert_eq!(parse_duration(&format!("1:{}", u64::MAX)), None);
+	}
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use chrono::DateTime;
+use itertools::Itertools;
+use anyhow::Result;
+
+use super::Provider;
+use crate::utils::{Json, Map, IteratorEx};
+use crate::config::ConfigFeedEntry;
+use crate::metrics::RequestBuilderEx;
+use crate::feeds::{Feed, Entry};
+
+const MAX_CON_REQUESTS: usize = 4;
+const DEFAULT_COUNT: usize = 10;
+const MAX_LENGTH: usize = 300;
+
+pub struct SteamProvider {
+	// Name and header image per app, they rarely change so they're only fetched once
+	details: HashMap<u64, AppData>,
+}
+
+impl SteamProvider {
+	pub fn new(_config: Json) -> Result<Self> {
+		Ok(SteamProvider { details: HashMap::new() })
+	}
+}
+
+#[derive(Deserialize)]
+pub(super) struct ProviderData {
+	apps: Vec<u64>,
+	filter: Option<NewsFilter>,
+	count: Option<usize>,
+}
+
+#[derive(Deserialize, Copy, Clone, Hash, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum NewsFilter {
+	// Items tagged as patch notes
+	PatchNotes,
+	// Official announcements posted on the Steam community hub
+	Announcements,
+}
+
+#[derive(Serialize)]
+struct Extra<'a> {
+	#[serde(rename="appId")]
+	app_id: u64,
+	game: Option<&'a str>,
+	author: &'a str,
+	#[serde(rename="feedLabel")]
+	feed_label: &'a str,
+	tags: &'a Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct NewsResponse {
+	appnews: AppNews,
+}
+
+#[derive(Deserialize)]
+struct AppNews {
+	newsitems: Vec<NewsItem>,
+}
+
+#[derive(Deserialize)]
+struct NewsItem {
+	gid: String,
+	title: String,
+	url: String,
+	author: String,
+	contents: String,
+	feedlabel: String,
+	date: i64,
+	#[serde(default)]
+	tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct AppDetails {
+	success: bool,
+	data: Option<AppData>,
+}
+
+#[derive(Deserialize, Clone)]
+struct AppData {
+	name: String,
+	header_image: String,
+}
+
+async fn get<T: DeserializeOwned>(client: &reqwest::Client, url: &str) -> Result<T> {
+	let bytes = client.get(url)
+	                  .send_metered()
+	                  .await?
+	                  .error_for_status()?
+	                  .bytes()
+	                  .await?;
+	
+	Ok(serde_json::from_slice(&bytes)?)
+}
+
+// Filtering is done by Steam, so the count applies to the filtered news
+fn news_url(app: u64, filter: Option<NewsFilter>, count: usize) -> String {
+	let filter = match filter {
+		None => "",
+		Some(NewsFilter::PatchNotes) => "&tags=patchnotes",
+		Some(NewsFilter::Announcements) => "&feeds=steam_community_announcements",
+	};
+	
+	format!("https://api.steampowered.com/ISteamNews/GetNewsForApp/v2/?appid={}&count={}&maxlength={}{}&format=json", app, count, MAX_LENGTH, filter)
+}
+
+async fn fetch_news(client: &reqwest::Client, app: u64, filter: Option<NewsFilter>, count: usize) -> Result<Vec<NewsItem>> {
+	let news: NewsResponse = get(client, &news_url(app, filter, count)).await?;
+	Ok(news.appnews.newsitems)
+}
+
+// Only used for the name and header image, news are still useful without it
+async fn fetch_details(client: &reqwest::Client, app: u64) -> Option<AppData> {
+	get::<Map<AppDetails>>(client, &format!("https://store.steampowered.com/api/appdetails?appids={}&filters=basic", app))
+		.await
+		.ok()
+		.and_then(|mut details| details.remove(&app.to_string()))
+		.filter(|details| details.success)
+		.and_then(|details| details.data)
+}
+
+#[async_trait(?Send)]
+impl Provider for SteamProvider {
+	async fn fetch(&mut self, config: Map<&ConfigFeedEntry>, client: reqwest::Client) -> Map<Feed> {
+		let client_ref = &client;
+		
+		// One request per app and filter, large enough for the largest feed
+		let mut requests: HashMap<(u64, Option<NewsFilter>), usize> = HashMap::new();
+		for data in config.values().flat_map(|entry| serde_json::from_value::<ProviderData>(entry.provider_data.clone())) {
+			for app in data.apps {
+				let count = requests.entry((app, data.filter)).or_default();
+				*count = (*count).max(data.count.unwrap_or(DEFAULT_COUNT));
+			}
+		}
+		
+		let results = requests.iter()
+		                      .into_stream()
+		                      .map(|(&(app, filter), &count)| async move { ((app, filter), fetch_news(client_ref, app, filter, count).await) })
+		                      .buffer_unordered(MAX_CON_REQUESTS)
+		                      .collect::<HashMap<_, _>>()
+		                      .await;
+		
+		let missing = requests.keys()
+		                      .map(|(app, _)| *app)
+		                      .filter(|app| !self.details.contains_key(app))
+		                      .unique()
+		                      .into_stream()
+		                      .map(|app| async move { (app, fetch_details(client_ref, app).await) })
+		                      .buffer_unordered(MAX_CON_REQUESTS)
+		                      .collect::<Vec<_>>()
+		                      .await;
+		
+		// Failed lookups are retried on the next fetch
+		self.details.extend(missing.into_iter().flat_map(|(app, details)| details.map(|details| (app, details))));
+		let details_cache = &self.details;
+		
+		config.into_iter()
+		      .map(|(name, entry)| {
+			      let data: ProviderData = match serde_json::from_value(entry.provider_data.clone()) {
+				      Ok(data) => data,
+				      Err(err) => return (name, Feed::from_err("Unable to parse providerData", &err.into())),
+			      };
+			      
+			      let mut feed = Feed::new();
+			      
+			      for app in &data.apps {
+				      let news = match results.get(&(*app, data.filter)) {
+					      Some(Ok(news)) => news,
+					      Some(Err(err)) => { feed.add_err(&format!("Unable to fetch news for app {}", app), err); continue },
+					      None => continue,
+				      };
+				      
+				      let details = details_cache.get(app);
+				      let header_image = details.map(|details| details.header_image.clone())
+				                                .unwrap_or_else(|| format!("https://cdn.akamai.steamstatic.com/steam/apps/{}/header.jpg", app));
+				      
+				      let entries = news.iter()
+				                        .take(data.count.unwrap_or(DEFAULT_COUNT))
+				                        .map(|item| {
+					                        Entry::new(&item.title, &item.gid)
+					                              .description(&item.contents)
+					                              .link(&item.url)
+					                              .image_url(&header_image)
+					                              .set_timestamp(DateTime::from_timestamp(item.date, 0))
+					                              .set_extra(serde_json::to_value(Extra {
+						                              app_id: *app,
+						                              game: details.map(|details| details.name.as_str()),
+						                              author: &item.author,
+						                              feed_label: &item.feedlabel,
+						                              tags: &item.tags,
+					                              }).ok())
+				                        });
+				      
+				      feed.notifications.extend(entries);
+			      }
+			      
+			      feed.notifications.sort();
+			      
+			      (name, feed)
+		      })
+		      .collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn filters_news_on_steam() {
+		assert_eq!(news_url(440, None, 10), "https://api.steampowered.com/ISteamNews/GetNewsForApp/v2/?appid=440&count=10&maxlength=300&format=json");
+		assert!(news_url(440, Some(NewsFilter::PatchNotes), 5).contains("&count=5&maxlength=300&tags=patchnotes&"));
+		assert!(news_url(440, Some(NewsFilter::Announcements), 5).contains("&feeds=steam_community_announcements&"));
+	}
+}
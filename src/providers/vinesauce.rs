@@ -8,6 +8,7 @@ use anyhow::Result;
 use super::Provider;
 use crate::utils::{Json, Map, hash};
 use crate::config::ConfigFeedEntry;
+use crate::metrics::RequestBuilderEx;
 use crate::feeds::{Feed, Entry};
 
 pub struct VinesauceProvider;
@@ -21,7 +22,7 @@ impl VinesauceProvider {
 type TeamData = Map<Member>;
 
 #[derive(Deserialize)]
-struct ProviderData {
+pub(super) struct ProviderData {
 	channels: Option<Vec<String>>,
 }
 
@@ -52,8 +53,9 @@ struct Channel {
 
 #[async_trait(?Send)]
 impl Provider for VinesauceProvider {
-	async fn fetch(&mut self, config: Map<&ConfigFeedEntry>, _client: reqwest::Client) -> Map<Feed> {
-		let team_data = reqwest::get("https://vinesauce.com/twitch/team-data-helix.json")
+	async fn fetch(&mut self, config: Map<&ConfigFeedEntry>, client: reqwest::Client) -> Map<Feed> {
+		let team_data = client.get("https://vinesauce.com/twitch/team-data-helix.json")
+		                        .send_metered()
 		                        .map_err(anyhow::Error::new)
 		                        .and_then(|res| res.bytes().map_err(Into::into))
 		                        .await
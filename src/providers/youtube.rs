@@ -11,9 +11,11 @@ use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use chrono::DateTime;
 use serde_json::json;
 use thiserror::Error;
+use tracing::warn;
 use crate::utils::{Json, Map, IteratorEx};
 use crate::providers::Provider;
 use crate::config::ConfigFeedEntry;
+use crate::metrics::{self, RequestBuilderEx};
 use crate::feeds::{Feed, Entry};
 
 const MAX_CON_REQUESTS: usize = 10;
@@ -71,7 +73,7 @@ impl YouTubeProvider {
 		let result = client.post("https://oauth2.googleapis.com/token")
 		                   .body(body)
 		                   .header("Content-Type", "application/x-www-form-urlencoded")
-		                   .send()
+		                   .send_metered()
 		                   .await?
 		                   .error_for_status()?
 		                   .bytes()
@@ -90,8 +92,12 @@ fn encode<'a>(text: &'a str) -> impl Display + 'a {
 	utf8_percent_encode(text, NON_ALPHANUMERIC)
 }
 
+// List calls cost 1 quota unit per page
 async fn fetch<Item: DeserializeOwned + std::fmt::Debug>(url: String) -> Result<Vec<Result<Item>>> {
-	let result = reqwest::get(&url)
+	metrics::record_youtube_quota(1);
+	let result = reqwest::Client::new()
+	                     .get(&url)
+	                     .send_metered()
 	                     .await?
 	                     .error_for_status()?
 	                     .bytes()
@@ -100,9 +106,8 @@ async fn fetch<Item: DeserializeOwned + std::fmt::Debug>(url: String) -> Result<
 	let result = serde_json::from_slice::<YouTubeResponse>(&*result)?;
 	
 	for r in result.items.iter() {
-		let w = serde_json::from_value::<Item>(r.clone());
-		if w.is_err() {
-			eprintln!("Couldn't parse {} {:?}", serde_json::to_string_pretty(r).unwrap(), w);
+		if let Err(err) = serde_json::from_value::<Item>(r.clone()) {
+			warn!(item = %r, error = %err, "Unable to parse YouTube item");
 		}
 	}
 	
@@ -120,7 +125,10 @@ async fn fetch_all<Item: DeserializeOwned>(url: String) -> Result<Vec<Result<Ite
 		let url = next_page_token.map(|token| format!("{}&pageToken={}", &url, encode(&token)))
 		                         .unwrap_or(url.to_string());
 		
-		let result = reqwest::get(&url)
+		metrics::record_youtube_quota(1);
+		let result = reqwest::Client::new()
+		                           .get(&url)
+		                           .send_metered()
 		                           .await?
 		                           .error_for_status()?
 		                           .bytes()
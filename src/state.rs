@@ -1,21 +1,42 @@
 use std::sync::Arc;
+use std::path::PathBuf;
+use std::time::Duration;
 use arc_swap::ArcSwap;
+use tokio::sync::{broadcast, Notify};
 
-use crate::feeds::Feeds;
+use crate::feeds::{Entry, Feeds};
 use crate::config::ConfigFeedEntry;
 use crate::utils::Map;
+use crate::providers::{ProviderStatus, FetchStatus};
 
 #[derive(Debug, Clone)]
 pub struct State {
 	pub feed_entries: Arc<ArcSwap<Map<ConfigFeedEntry>>>,
 	pub feeds: Arc<ArcSwap<Feeds>>,
+	// Updated after every fetch
+	pub providers: Arc<ArcSwap<Map<ProviderStatus>>>,
+	// Times of the last and the next fetch
+	pub fetch: Arc<ArcSwap<FetchStatus>>,
+	// Entries not present in the previous snapshot, sent after every fetch (even if there are none)
+	pub new_entries: broadcast::Sender<Arc<Vec<Entry>>>,
+	// Triggers fetch without waiting for the fetch interval
+	pub refresh: Arc<Notify>,
+	// Config file the feed entries were loaded from, feeds changed at runtime are saved to it
+	pub config_path: Arc<PathBuf>,
+	pub fetch_interval: Duration,
 }
 
 impl State {
-	pub(crate) fn new(feed_entries: Map<ConfigFeedEntry>) -> Self {
+	pub(crate) fn new(feed_entries: Map<ConfigFeedEntry>, config_path: PathBuf, fetch_interval: Duration) -> Self {
 		State {
 			feed_entries: Arc::new(ArcSwap::from_pointee(feed_entries)),
 			feeds: Arc::new(ArcSwap::from_pointee(Feeds::new())),
+			providers: Arc::new(ArcSwap::from_pointee(Map::new())),
+			fetch: Arc::new(ArcSwap::from_pointee(FetchStatus::default())),
+			new_entries: broadcast::channel(16).0,
+			refresh: Arc::new(Notify::new()),
+			config_path: Arc::new(config_path),
+			fetch_interval,
 		}
 	}
 }
@@ -1,9 +1,18 @@
 use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
 use std::hash::{Hash, Hasher};
 use std::collections::hash_map::DefaultHasher;
 use futures::stream;
 use futures::stream::Iter;
 use itertools::Itertools;
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tokio::net::UnixListener;
+use anyhow::Result;
+use thiserror::Error;
 
 use crate::feeds::Feed;
 
@@ -16,6 +25,76 @@ pub fn hash<H: Hash>(data: &H) -> String {
 	format!("{:X}", state.finish())
 }
 
+// Loads state persisted in the data directory, missing file results in default value
+pub async fn load_data<T: DeserializeOwned + Default>(path: impl AsRef<Path>) -> Result<T> {
+	match tokio::fs::read(path).await {
+		Ok(content) => Ok(serde_json::from_slice(&content)?),
+		Err(err) if err.kind() == ErrorKind::NotFound => Ok(T::default()),
+		Err(err) => Err(err.into()),
+	}
+}
+
+pub async fn save_data<T: Serialize>(path: impl AsRef<Path>, data: &T) -> Result<()> {
+	let path = path.as_ref();
+	if let Some(dir) = path.parent() {
+		tokio::fs::create_dir_all(dir).await?;
+	}
+	
+	// Write to temporary file first, so a crash doesn't leave half written state behind
+	let tmp = path.with_extension("tmp");
+	tokio::fs::write(&tmp, serde_json::to_vec_pretty(data)?).await?;
+	tokio::fs::rename(&tmp, path).await?;
+	
+	Ok(())
+}
+
+// Socket is bound in a private directory and moved into place once it has the requested permissions,
+// so it's never reachable with wider ones. Only sockets left over by previous runs are replaced
+pub async fn bind_unix_socket(path: &Path, mode: u32) -> Result<UnixListener> {
+	let dir = path.parent().unwrap_or(Path::new("."));
+	let name = path.file_name().ok_or_else(|| NotASocketError(path.to_path_buf()))?;
+	tokio::fs::create_dir_all(dir).await?;
+	
+	match tokio::fs::symlink_metadata(path).await {
+		Ok(metadata) if metadata.file_type().is_socket() => tokio::fs::remove_file(path).await?,
+		Ok(_) => return Err(NotASocketError(path.to_path_buf()).into()),
+		Err(err) if err.kind() == ErrorKind::NotFound => {},
+		Err(err) => return Err(err.into()),
+	}
+	
+	let private = dir.join(format!(".{}.{}", name.to_string_lossy(), std::process::id()));
+	let _ = tokio::fs::remove_dir_all(&private).await;
+	tokio::fs::DirBuilder::new().mode(0o700).create(&private).await?;
+	
+	let result: Result<UnixListener> = async {
+		let tmp = private.join("socket");
+		let listener = UnixListener::bind(&tmp)?;
+		tokio::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(mode)).await?;
+		tokio::fs::rename(&tmp, path).await?;
+		Ok(listener)
+	}.await;
+	
+	tokio::fs::remove_dir_all(&private).await?;
+	result
+}
+
+// Regexes from configs and queries, limited so they can't be expensive to build or match
+pub fn build_regex(regex: &str) -> Result<Regex, regex::Error> {
+	RegexBuilder::new(regex)
+	             .size_limit(1024 * 32)
+	             .dfa_size_limit(1024 * 32)
+	             .nest_limit(10)
+	             .case_insensitive(true)
+	             .build()
+}
+
+pub fn escape_html(text: &str) -> String {
+	text.replace('&', "&amp;")
+	    .replace('<', "&lt;")
+	    .replace('>', "&gt;")
+	    .replace('"', "&quot;")
+}
+
 pub trait IteratorEx: Iterator + Sized {
 	fn into_stream(self) -> Iter<Self> {
 		stream::iter(self)
@@ -40,3 +119,57 @@ pub trait IteratorEx: Iterator + Sized {
 }
 
 impl<I> IteratorEx for I where I: Iterator {}
+
+#[derive(Debug, Clone, Error)]
+#[error("{0} exists and is not a socket")]
+pub struct NotASocketError(PathBuf);
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn temp_dir(name: &str) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("rust-notifier-{}-{}", name, std::process::id()));
+		let _ = std::fs::remove_dir_all(&dir);
+		std::fs::create_dir_all(&dir).unwrap();
+		dir
+	}
+	
+	#[tokio::test]
+	async fn binds_socket_with_mode() {
+		let dir = temp_dir("bind");
+		let path = dir.join("test.sock");
+		
+		let listener = bind_unix_socket(&path, 0o600).await.unwrap();
+		let metadata = std::fs::symlink_metadata(&path).unwrap();
+		assert!(metadata.file_type().is_socket());
+		assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+		drop(listener);
+		
+		// Left over from the previous run
+		bind_unix_socket(&path, 0o660).await.unwrap();
+		assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o660);
+		assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+		
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+	
+	#[tokio::test]
+	async fn keeps_other_files() {
+		let dir = temp_dir("keep");
+		let path = dir.join("config.json");
+		std::fs::write(&path, "{}").unwrap();
+		
+		assert!(bind_unix_socket(&path, 0o600).await.is_err());
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}");
+		
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+	
+	#[test]
+	fn limits_regexes() {
+		assert!(build_regex("NEWS").unwrap().is_match("news"));
+		assert!(build_regex(&"(".repeat(20)).is_err());
+		assert!(build_regex("a{1000}{1000}").is_err());
+	}
+}